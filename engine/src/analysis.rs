@@ -0,0 +1,183 @@
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use libtetris::{Board, ColoredRow, FallingPiece, Piece};
+use serde::{Deserialize, Serialize};
+
+use crate::bot;
+
+/// A placement that loses more than this much evaluation compared to the bot's is a blunder.
+pub const BLUNDER_THRESHOLD: i32 = 300;
+
+/// Every placement made in a game, which is all that's needed to analyse it.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub placements: Vec<RecordedPlacement>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedPlacement {
+    /// The board right before the piece was placed, with the rest of the queue and hold.
+    pub board: Board<ColoredRow>,
+    pub piece: FallingPiece,
+}
+
+/// How the player's placement compares to the best one the bot could find for the same piece.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlacementAnalysis {
+    pub board: Board<ColoredRow>,
+    pub played: FallingPiece,
+    /// The evaluator's score of the player's placement, or `None` if the bot's search can't make
+    /// it, such as when it needs a kick the bot doesn't know about.
+    pub played_score: Option<i32>,
+    pub best: FallingPiece,
+    pub best_score: i32,
+}
+
+impl PlacementAnalysis {
+    /// How much evaluation the player gave up by not playing the bot's move, if the player's
+    /// placement could be analysed.
+    pub fn loss(&self) -> Option<i32> {
+        self.played_score.map(|played| self.best_score - played)
+    }
+
+    pub fn is_blunder(&self) -> bool {
+        self.loss().map_or(false, |loss| loss > BLUNDER_THRESHOLD)
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary {
+            piece: self.played.kind.0,
+            played: self.played.cells(),
+            played_score: self.played_score,
+            best: self.best.cells(),
+            best_score: self.best_score,
+            loss: self.loss(),
+            blunder: self.is_blunder(),
+        }
+    }
+}
+
+/// A [`PlacementAnalysis`] without the board, printed when analysing headless.
+#[derive(Clone, Debug, Serialize)]
+pub struct Summary {
+    pub piece: Piece,
+    pub played: [(i32, i32); 4],
+    pub played_score: Option<i32>,
+    pub best: [(i32, i32); 4],
+    pub best_score: i32,
+    pub loss: Option<i32>,
+    pub blunder: bool,
+}
+
+/// Analyses a game on another thread, as that takes a while for long games.
+pub struct AnalysisJob {
+    receiver: Receiver<Vec<PlacementAnalysis>>,
+}
+
+impl AnalysisJob {
+    /// Analyse the game `record` returns, which is called on the other thread too.
+    pub fn start(record: impl FnOnce() -> GameRecord + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // nobody is waiting for the result anymore if sending fails
+            let _ = sender.send(analyse(&record()));
+        });
+        Self { receiver }
+    }
+
+    /// The analysis, once it's done.
+    pub fn poll(&self) -> Option<Vec<PlacementAnalysis>> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// Analyse every placement of a game. The bot's move is the placement of the same piece its
+/// evaluator scores best, without holding. Placements the bot can't make itself are kept but left
+/// unanalysed, so they aren't compared against some other placement. Pieces the bot can't place at
+/// all, which only happens when it would top out, are skipped.
+pub fn analyse(record: &GameRecord) -> Vec<PlacementAnalysis> {
+    record.placements.iter()
+        .filter_map(|placement| {
+            let scores = bot::score_placements(&placement.board, placement.piece.kind.0);
+            let &(best, best_score) = scores.iter().max_by_key(|(_, score)| *score)?;
+
+            let mut played_cells = placement.piece.cells();
+            played_cells.sort_unstable();
+            let played_score = scores.iter()
+                .find(|(location, _)| {
+                    let mut cells = location.cells();
+                    cells.sort_unstable();
+                    cells == played_cells
+                })
+                .map(|&(_, score)| score);
+
+            Some(PlacementAnalysis {
+                board: placement.board.clone(),
+                played: placement.piece,
+                played_score,
+                best,
+                best_score,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use libtetris::{PieceState, RotationState, TspinStatus};
+
+    use super::*;
+
+    /// Four rows filled up to a well on the right, with an I to play.
+    fn tetris_ready() -> Board<ColoredRow> {
+        let mut field = [[false; 10]; 40];
+        for row in &mut field[..4] {
+            *row = [true, true, true, true, true, true, true, true, true, false];
+        }
+        let bag = Board::<ColoredRow>::new().bag;
+        let mut board = Board::<ColoredRow>::new_with_state(field, bag, None, false, 0);
+        for piece in [Piece::T, Piece::O, Piece::L] {
+            board.add_next_piece(piece);
+        }
+        board
+    }
+
+    fn flat_i(x: i32, y: i32) -> FallingPiece {
+        FallingPiece { kind: PieceState(Piece::I, RotationState::North), x, y, tspin: TspinStatus::None }
+    }
+
+    fn analyse_one(board: Board<ColoredRow>, piece: FallingPiece) -> Vec<PlacementAnalysis> {
+        analyse(&GameRecord { placements: vec![RecordedPlacement { board, piece }] })
+    }
+
+    #[test]
+    fn covering_the_well_is_a_blunder() {
+        // lay the I flat on top of the stack, right up against the wall so it covers the well
+        let mut played = flat_i(0, 4);
+        let right = played.cells().iter().map(|&(x, _)| x).max().unwrap();
+        played.x += 9 - right;
+
+        let analysis = analyse_one(tetris_ready(), played);
+        assert_eq!(analysis.len(), 1);
+        assert!(analysis[0].played_score.is_some());
+        assert!(analysis[0].is_blunder(), "loss was {:?}", analysis[0].loss());
+        assert_ne!(sorted(analysis[0].best), sorted(played));
+    }
+
+    #[test]
+    fn placements_the_bot_cant_make_are_unanalysed() {
+        // an I floating in the middle of an empty board can't have been dropped there
+        let analysis = analyse_one(Board::new(), flat_i(4, 10));
+        assert_eq!(analysis.len(), 1);
+        assert_eq!(analysis[0].played_score, None);
+        assert_eq!(analysis[0].loss(), None);
+        assert!(!analysis[0].is_blunder());
+    }
+
+    fn sorted(piece: FallingPiece) -> [(i32, i32); 4] {
+        let mut cells = piece.cells();
+        cells.sort_unstable();
+        cells
+    }
+}
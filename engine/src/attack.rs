@@ -0,0 +1,238 @@
+use libtetris::{LockResult, PlacementKind};
+use serde::{Deserialize, Serialize};
+
+/// The rules deciding how many lines of garbage a placement sends.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AttackTable {
+    /// Modern guideline games such as Tetris 99: a short combo table and 10 lines for a perfect
+    /// clear.
+    Guideline,
+    /// Puyo Puyo Tetris, with a slower-growing combo table.
+    Ppt,
+    /// TETR.IO: combos multiply the attack of every clear, and back-to-back bonuses grow with the
+    /// length of the back-to-back chain.
+    TetrIo,
+    Custom(CustomAttack),
+}
+
+/// An attack table with a value for every kind of clear.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CustomAttack {
+    pub single: u32,
+    pub double: u32,
+    pub triple: u32,
+    pub tetris: u32,
+    pub mini_tspin_single: u32,
+    pub mini_tspin_double: u32,
+    pub tspin_single: u32,
+    pub tspin_double: u32,
+    pub tspin_triple: u32,
+    pub back_to_back: u32,
+    pub perfect_clear: u32,
+    /// The bonus of every clear in a combo; the last value is used for longer combos.
+    pub combo: [u32; 12],
+}
+
+const GUIDELINE_COMBO: [u32; 12] = [0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5, 5];
+const PPT_COMBO: [u32; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+
+impl Default for CustomAttack {
+    fn default() -> Self {
+        CustomAttack {
+            single: 0,
+            double: 1,
+            triple: 2,
+            tetris: 4,
+            mini_tspin_single: 0,
+            mini_tspin_double: 1,
+            tspin_single: 2,
+            tspin_double: 4,
+            tspin_triple: 6,
+            back_to_back: 1,
+            perfect_clear: 10,
+            combo: GUIDELINE_COMBO,
+        }
+    }
+}
+
+impl CustomAttack {
+    fn base(&self, kind: PlacementKind) -> u32 {
+        match kind {
+            PlacementKind::Clear1 => self.single,
+            PlacementKind::Clear2 => self.double,
+            PlacementKind::Clear3 => self.triple,
+            PlacementKind::Clear4 => self.tetris,
+            PlacementKind::MiniTspin1 => self.mini_tspin_single,
+            PlacementKind::MiniTspin2 => self.mini_tspin_double,
+            PlacementKind::Tspin1 => self.tspin_single,
+            PlacementKind::Tspin2 => self.tspin_double,
+            PlacementKind::Tspin3 => self.tspin_triple,
+            _ => 0,
+        }
+    }
+
+    fn attack(&self, kind: PlacementKind, combo: Option<u32>, perfect_clear: bool, b2b_chain: u32) -> u32 {
+        let Some(combo) = combo else { return 0; };
+        let mut attack = self.base(kind) + self.combo[(combo as usize).min(self.combo.len() - 1)];
+        if b2b_chain > 1 {
+            attack += self.back_to_back;
+        }
+        if perfect_clear {
+            attack += self.perfect_clear;
+        }
+        attack
+    }
+}
+
+impl AttackTable {
+    /// The attack of a placement. `b2b_chain` is the number of difficult clears in a row,
+    /// including this one if it is one.
+    pub fn attack(&self, locked: &LockResult, b2b_chain: u32) -> u32 {
+        self.clear_attack(locked.placement_kind, locked.combo, locked.perfect_clear, b2b_chain)
+    }
+
+    /// The attack of a placement of the given kind, `combo` being `None` if it cleared no lines.
+    fn clear_attack(&self, kind: PlacementKind, combo: Option<u32>, perfect_clear: bool, b2b_chain: u32) -> u32 {
+        match self {
+            AttackTable::Guideline => CustomAttack::default().attack(kind, combo, perfect_clear, b2b_chain),
+            AttackTable::Ppt => CustomAttack {
+                combo: PPT_COMBO,
+                ..CustomAttack::default()
+            }.attack(kind, combo, perfect_clear, b2b_chain),
+            AttackTable::TetrIo => tetrio_attack(kind, combo, perfect_clear, b2b_chain),
+            AttackTable::Custom(table) => table.attack(kind, combo, perfect_clear, b2b_chain),
+        }
+    }
+}
+
+fn tetrio_attack(kind: PlacementKind, combo: Option<u32>, perfect_clear: bool, b2b_chain: u32) -> u32 {
+    let Some(combo) = combo else { return 0; };
+    let base = CustomAttack::default().base(kind);
+
+    // the first back-to-back is worth one line, and every level after that takes longer to reach
+    let b2b_level = b2b_chain.saturating_sub(1);
+    let b2b = match b2b_level {
+        0 => 0,
+        1..=2 => 1,
+        3..=7 => 2,
+        8..=23 => 3,
+        24..=66 => 4,
+        _ => 5,
+    };
+
+    let lines = (base + b2b) as f32;
+    let mut attack = if lines == 0. && combo >= 2 {
+        // clears that wouldn't send anything on their own still do in a long enough combo
+        (1. + 1.25 * combo as f32).ln().floor()
+    } else {
+        (lines * (1. + 0.25 * combo as f32)).floor()
+    } as u32;
+    if perfect_clear {
+        attack += 10;
+    }
+    attack
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The attack of a clear that isn't part of a back-to-back chain or a perfect clear.
+    fn plain(table: AttackTable, kind: PlacementKind, combo: u32) -> u32 {
+        table.clear_attack(kind, Some(combo), false, 0)
+    }
+
+    #[test]
+    fn nothing_is_sent_without_a_clear() {
+        for table in [AttackTable::Guideline, AttackTable::Ppt, AttackTable::TetrIo] {
+            assert_eq!(table.clear_attack(PlacementKind::None, None, false, 0), 0);
+            assert_eq!(table.clear_attack(PlacementKind::Tspin, None, false, 5), 0);
+        }
+    }
+
+    #[test]
+    fn guideline_table() {
+        let table = AttackTable::Guideline;
+        assert_eq!(plain(table, PlacementKind::Clear1, 0), 0);
+        assert_eq!(plain(table, PlacementKind::Clear2, 0), 1);
+        assert_eq!(plain(table, PlacementKind::Clear3, 0), 2);
+        assert_eq!(plain(table, PlacementKind::Clear4, 0), 4);
+        assert_eq!(plain(table, PlacementKind::MiniTspin1, 0), 0);
+        assert_eq!(plain(table, PlacementKind::Tspin1, 0), 2);
+        assert_eq!(plain(table, PlacementKind::Tspin2, 0), 4);
+        assert_eq!(plain(table, PlacementKind::Tspin3, 0), 6);
+
+        // combos
+        assert_eq!(plain(table, PlacementKind::Clear1, 1), 1);
+        assert_eq!(plain(table, PlacementKind::Clear2, 4), 3);
+        assert_eq!(plain(table, PlacementKind::Clear1, 11), 5);
+        assert_eq!(plain(table, PlacementKind::Clear1, 50), 5);
+
+        // back-to-back starts with the second difficult clear in a row
+        assert_eq!(table.clear_attack(PlacementKind::Clear4, Some(0), false, 1), 4);
+        assert_eq!(table.clear_attack(PlacementKind::Clear4, Some(0), false, 2), 5);
+        assert_eq!(table.clear_attack(PlacementKind::Tspin2, Some(0), false, 10), 5);
+
+        assert_eq!(table.clear_attack(PlacementKind::Clear4, Some(0), true, 0), 14);
+        assert_eq!(table.clear_attack(PlacementKind::Clear2, Some(2), true, 0), 12);
+    }
+
+    #[test]
+    fn ppt_combos_grow_slower() {
+        let table = AttackTable::Ppt;
+        assert_eq!(plain(table, PlacementKind::Clear2, 0), 1);
+        assert_eq!(plain(table, PlacementKind::Clear1, 1), 0);
+        assert_eq!(plain(table, PlacementKind::Clear1, 2), 1);
+        assert_eq!(plain(table, PlacementKind::Clear1, 4), 2);
+        assert_eq!(plain(table, PlacementKind::Clear1, 10), 4);
+        assert_eq!(plain(table, PlacementKind::Clear1, 50), 5);
+
+        assert_eq!(table.clear_attack(PlacementKind::Tspin2, Some(0), false, 2), 5);
+        assert_eq!(table.clear_attack(PlacementKind::Clear4, Some(0), true, 0), 14);
+    }
+
+    #[test]
+    fn tetrio_table() {
+        let table = AttackTable::TetrIo;
+        assert_eq!(plain(table, PlacementKind::Clear1, 0), 0);
+        assert_eq!(plain(table, PlacementKind::Clear4, 0), 4);
+        assert_eq!(plain(table, PlacementKind::Tspin2, 0), 4);
+
+        // combos multiply the attack of the clear
+        assert_eq!(plain(table, PlacementKind::Clear2, 4), 2);
+        assert_eq!(plain(table, PlacementKind::Clear4, 2), 6);
+        // clears that send nothing only do in a combo of two or more
+        assert_eq!(plain(table, PlacementKind::Clear1, 1), 0);
+        assert_eq!(plain(table, PlacementKind::Clear1, 2), 1);
+        assert_eq!(plain(table, PlacementKind::Clear1, 5), 1);
+        assert_eq!(plain(table, PlacementKind::Clear1, 6), 2);
+
+        // back-to-back levels
+        let b2b = |chain| table.clear_attack(PlacementKind::Clear4, Some(0), false, chain);
+        assert_eq!(b2b(1), 4);
+        assert_eq!(b2b(2), 5);
+        assert_eq!(b2b(3), 5);
+        assert_eq!(b2b(4), 6);
+        assert_eq!(b2b(9), 7);
+        assert_eq!(b2b(25), 8);
+        assert_eq!(b2b(68), 9);
+
+        assert_eq!(table.clear_attack(PlacementKind::Clear4, Some(0), true, 0), 14);
+    }
+
+    #[test]
+    fn custom_table() {
+        let table = AttackTable::Custom(CustomAttack {
+            single: 1,
+            back_to_back: 2,
+            perfect_clear: 5,
+            combo: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            ..CustomAttack::default()
+        });
+        assert_eq!(plain(table, PlacementKind::Clear1, 0), 1);
+        assert_eq!(plain(table, PlacementKind::Clear1, 3), 4);
+        assert_eq!(plain(table, PlacementKind::Clear1, 30), 12);
+        assert_eq!(table.clear_attack(PlacementKind::Clear4, Some(0), false, 2), 6);
+        assert_eq!(table.clear_attack(PlacementKind::Clear1, Some(0), true, 0), 6);
+    }
+}
@@ -0,0 +1,397 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use cold_clear::{Interface, Options};
+use cold_clear::evaluation::{Evaluator, Standard};
+use gilrs::Gamepad;
+use libtetris::*;
+
+use crate::game::Event;
+use crate::input::{GameAction, InputSource, Key};
+
+/// How many nodes Cold Clear searches before suggesting a placement.
+const HINT_NODES: u32 = 20_000;
+
+/// A placement the bot would consider making.
+#[derive(Copy, Clone, Debug)]
+pub struct Suggestion {
+    pub location: FallingPiece,
+    /// Whether the piece has to be held first to make this placement.
+    pub hold: bool,
+    /// How the evaluator scores the placement, if it found it.
+    pub score: Option<i32>,
+}
+
+/// Converts a board as used by the game into one the bot understands.
+pub fn bot_board(board: &Board<ColoredRow>) -> Board {
+    let mut bot_board = Board::new_with_state(
+        board.get_field(),
+        board.bag,
+        board.hold_piece,
+        board.b2b_bonus,
+        board.combo,
+    );
+    for piece in board.next_queue() {
+        bot_board.add_next_piece(piece);
+    }
+    bot_board
+}
+
+/// The options Cold Clear is launched with, searching at most `max_nodes` nodes.
+fn options(max_nodes: u32) -> Options {
+    Options {
+        spawn_rule: SpawnRule::Row21AndFall,
+        max_nodes,
+        ..Default::default()
+    }
+}
+
+/// Start Cold Clear on `board`, with `current` as the piece it plays first.
+fn launch(board: &Board<ColoredRow>, current: Piece, options: Options) -> Interface {
+    let mut bot_board = Board::new_with_state(
+        board.get_field(),
+        board.bag,
+        board.hold_piece,
+        board.b2b_bonus,
+        board.combo,
+    );
+    bot_board.add_next_piece(current);
+    for piece in board.next_queue() {
+        bot_board.add_next_piece(piece);
+    }
+    Interface::launch(bot_board, options, Standard::default(), None)
+}
+
+/// Looks for suggestions on another thread, so the game keeps running while the bot thinks.
+pub struct HintSearch {
+    receiver: Receiver<Vec<Suggestion>>,
+}
+
+impl HintSearch {
+    /// Start looking for the best `count` placements of `current`, see [`suggest`].
+    pub fn start(board: &Board<ColoredRow>, current: Piece, incoming: u32, count: usize) -> Self {
+        let board = board.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // nobody is waiting for the result anymore if sending fails
+            let _ = sender.send(suggest(&board, current, incoming, count));
+        });
+        Self { receiver }
+    }
+
+    /// The suggestions, once the search is done.
+    pub fn poll(&self) -> Option<Vec<Suggestion>> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// The best `count` placements of `current`, best first, blocking until they are found.
+///
+/// The first is the placement Cold Clear would make, with `incoming` lines of garbage on the way.
+/// The others are the placements of `current`, or of the piece played instead of it if it were
+/// held, that Cold Clear's evaluator scores best, looking one piece further ahead.
+pub fn suggest(board: &Board<ColoredRow>, current: Piece, incoming: u32, count: usize) -> Vec<Suggestion> {
+    let interface = launch(board, current, Options { min_nodes: HINT_NODES, ..options(HINT_NODES) });
+    interface.suggest_next_move(incoming);
+    let best = interface.block_next_move();
+
+    let mut suggestions = rank(board, current);
+    if let Some((mv, _)) = best {
+        let cells = sorted_cells(&mv.expected_location);
+        let score = suggestions.iter()
+            .position(|s| s.hold == mv.hold && sorted_cells(&s.location) == cells)
+            .and_then(|idx| suggestions.remove(idx).score);
+        suggestions.insert(0, Suggestion { location: mv.expected_location, hold: mv.hold, score });
+    }
+    suggestions.truncate(count);
+    suggestions
+}
+
+fn sorted_cells(piece: &FallingPiece) -> [(i32, i32); 4] {
+    let mut cells = piece.cells();
+    cells.sort_unstable();
+    cells
+}
+
+/// Every placement of `current`, and of the piece that would be played instead of it if it were
+/// held, as scored by the evaluator, best first.
+fn rank(board: &Board<ColoredRow>, current: Piece) -> Vec<Suggestion> {
+    let evaluator = Standard::default();
+    let board = bot_board(board);
+    let queue: Vec<Piece> = board.next_queue().collect();
+
+    // the piece played when holding, and the queue that is left afterwards
+    let (held, held_queue) = match board.hold_piece {
+        Some(hold) => (Some(hold), &queue[..]),
+        None => (queue.first().copied(), queue.get(1..).unwrap_or(&[])),
+    };
+
+    let mut suggestions = vec![];
+    for (piece, hold, queue) in [(Some(current), false, &queue[..]), (held, true, held_queue)] {
+        let Some(piece) = piece else { continue; };
+        if hold && piece == current {
+            continue;
+        }
+        for (location, score) in search(&evaluator, &board, piece, queue.first().copied()) {
+            suggestions.push(Suggestion { location, hold, score: Some(score) });
+        }
+    }
+
+    suggestions.sort_by_key(|s| std::cmp::Reverse(s.score));
+    suggestions
+}
+
+/// Score every placement of `piece` on `board`, the same way [`suggest`] scores the evaluator's
+/// placements, without considering hold.
+pub fn score_placements(board: &Board<ColoredRow>, piece: Piece) -> Vec<(FallingPiece, i32)> {
+    let board = bot_board(board);
+    let next = board.next_queue().next();
+    search(&Standard::default(), &board, piece, next)
+}
+
+/// Score every placement of `piece` on `board`, taking the best follow-up placement of `next` into
+/// account if it is known.
+fn search(
+    evaluator: &Standard,
+    board: &Board,
+    piece: Piece,
+    next: Option<Piece>,
+) -> Vec<(FallingPiece, i32)> {
+    placements(evaluator, board, piece)
+        .into_iter()
+        .map(|(location, result, value, reward)| {
+            let follow_up = next.and_then(|next| {
+                placements(evaluator, &result, next)
+                    .into_iter()
+                    .map(|(_, _, value, reward)| value + reward)
+                    .max()
+            });
+            // the board's value is judged after the follow-up if there is one, like the bot
+            // would at the end of its search
+            let score = match follow_up {
+                Some(follow_up) => reward + follow_up,
+                None => value + reward,
+            };
+            (location, score)
+        })
+        .collect()
+}
+
+/// Every placement of `piece` on `board`, with the board it results in, the evaluator's value of
+/// that board and the reward for getting there.
+fn placements(evaluator: &Standard, board: &Board, piece: Piece) -> Vec<(FallingPiece, Board, i32, i32)> {
+    let Some(spawned) = SpawnRule::Row21AndFall.spawn(piece, board) else { return vec![]; };
+    find_moves(board, spawned, MovementMode::ZeroG)
+        .into_iter()
+        .map(|placement| {
+            let mut result = board.clone();
+            let lock = result.lock_piece(placement.location);
+            let (value, reward) = evaluator.evaluate(&lock, &result, placement.inputs.time, piece);
+            (placement.location, result, value.value, reward.value)
+        })
+        .collect()
+}
+
+/// How strong and how fast the bot opponent plays.
+#[derive(Copy, Clone, Debug)]
+pub struct BotConfig {
+    /// The most pieces per second the bot will place.
+    pub pps: f32,
+    /// How many nodes the bot searches before deciding on a move; more is stronger.
+    pub max_nodes: u32,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            pps: 1.5,
+            max_nodes: 10_000,
+        }
+    }
+}
+
+enum BotState {
+    /// Waiting for a piece to spawn, or for the PPS cap to allow the next move.
+    Waiting,
+    Thinking,
+    Executing {
+        hold: bool,
+        movements: VecDeque<PieceMovement>,
+        expected: FallingPiece,
+        /// Inputs are only registered when pressed, so every input is followed by a tick without
+        /// any.
+        released: bool,
+        /// Soft drop is held until the piece lands, as how fast it falls depends on the config.
+        dropping: bool,
+    },
+}
+
+/// Plays a game by pressing the buttons a player would, using Cold Clear to decide on moves.
+pub struct BotInput {
+    config: BotConfig,
+    interface: Option<Interface>,
+    state: BotState,
+    controller: Controller,
+    /// Ticks since the bot last placed a piece, to keep to its PPS cap.
+    since_placed: u32,
+    /// Wait for the bot to decide on a move rather than letting the game run while it thinks.
+    blocking: bool,
+}
+
+impl BotInput {
+    pub fn new(config: BotConfig) -> Self {
+        Self {
+            config,
+            interface: None,
+            state: BotState::Waiting,
+            controller: Controller::default(),
+            since_placed: 0,
+            blocking: false,
+        }
+    }
+
+    /// A bot that makes the same moves every time it plays the same game, for games played
+    /// without a window. Each move blocks until the bot has searched all of its nodes.
+    pub fn blocking(config: BotConfig) -> Self {
+        Self {
+            blocking: true,
+            ..Self::new(config)
+        }
+    }
+
+    /// The buttons to hold next tick, `landed` being whether the piece can't fall any further.
+    fn next_controller(&mut self, landed: bool) -> Controller {
+        let BotState::Executing { hold, movements, released, dropping, .. } = &mut self.state else {
+            return Controller::default();
+        };
+
+        if *dropping {
+            if !landed {
+                return Controller { soft_drop: true, ..Controller::default() };
+            }
+            // let go of soft drop for a tick before the next input
+            *dropping = false;
+            *released = false;
+            return Controller::default();
+        }
+
+        *released = !*released;
+        if !*released {
+            return Controller::default();
+        }
+
+        let mut controller = Controller::default();
+        if *hold {
+            *hold = false;
+            controller.hold = true;
+        } else {
+            match movements.pop_front() {
+                Some(PieceMovement::Left) => controller.left = true,
+                Some(PieceMovement::Right) => controller.right = true,
+                Some(PieceMovement::Cw) => controller.rotate_right = true,
+                Some(PieceMovement::Ccw) => controller.rotate_left = true,
+                Some(PieceMovement::SonicDrop) => {
+                    controller.soft_drop = true;
+                    *dropping = true;
+                }
+                None => controller.hard_drop = true,
+            }
+        }
+        controller
+    }
+}
+
+impl InputSource for BotInput {
+    fn controller(&self, _: &HashSet<Key>, _: Option<Gamepad<'_>>) -> Controller {
+        self.controller
+    }
+
+    fn actions(&self, _: &HashSet<Key>, _: Option<Gamepad<'_>>) -> GameAction {
+        GameAction::default()
+    }
+
+    fn update(&mut self, board: &Board<ColoredRow>, events: &[Event], incoming: u32) {
+        self.since_placed += 1;
+
+        // the board already knows about the piece that spawned when the bot is launched
+        let mut launched = false;
+        if self.interface.is_none() {
+            let current = events.iter().find_map(|e| match e {
+                Event::PieceFalling(piece, _) => Some(piece.kind.0),
+                _ => None,
+            });
+            if let Some(current) = current {
+                let mut options = options(self.config.max_nodes);
+                if self.blocking {
+                    // searching as far on a single thread every move keeps the search deterministic
+                    options.min_nodes = options.max_nodes;
+                    options.threads = 1;
+                }
+                self.interface = Some(launch(board, current, options));
+                launched = true;
+            }
+        }
+        let Some(interface) = &mut self.interface else { return; };
+
+        let mut falling = false;
+        let mut landed = false;
+        for event in events {
+            match event {
+                Event::PieceSpawned { new_in_queue } if !launched => interface.add_next_piece(*new_in_queue),
+                Event::PieceFalling(piece, ghost) => {
+                    falling = true;
+                    landed = piece.y == ghost.y;
+                }
+                Event::PiecePlaced { piece, .. } => {
+                    // the bot misdropped, so its idea of the board has to be corrected
+                    if let BotState::Executing { expected, .. } = self.state {
+                        if expected.cells() != piece.cells() {
+                            interface.reset(board.get_field(), board.b2b_bonus, board.combo);
+                        }
+                    }
+                    self.state = BotState::Waiting;
+                    self.since_placed = 0;
+                }
+                Event::GarbageAdded(_) => interface.reset(board.get_field(), board.b2b_bonus, board.combo),
+                _ => {}
+            }
+        }
+
+        match self.state {
+            BotState::Waiting => {
+                let min_ticks = (60. / self.config.pps) as u32;
+                if falling && self.since_placed >= min_ticks {
+                    interface.suggest_next_move(incoming);
+                    self.state = BotState::Thinking;
+                }
+            }
+            BotState::Thinking => {
+                let next = match self.blocking {
+                    true => interface.block_next_move(),
+                    false => interface.poll_next_move().ok(),
+                };
+                if let Some((mv, _)) = next {
+                    interface.play_next_move(mv.expected_location);
+                    self.state = BotState::Executing {
+                        hold: mv.hold,
+                        movements: mv.inputs.into_iter().collect(),
+                        expected: mv.expected_location,
+                        released: true,
+                        dropping: false,
+                    };
+                }
+            }
+            BotState::Executing { .. } => {}
+        }
+
+        self.controller = self.next_controller(landed);
+    }
+
+    fn reset(&mut self) {
+        *self = Self {
+            blocking: self.blocking,
+            ..Self::new(self.config)
+        };
+    }
+}
@@ -0,0 +1,522 @@
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use libtetris::CellColor;
+use serde::{Deserialize, Serialize};
+use crate::game::GameConfig;
+use crate::input::{Config, GamepadControl, Key, UserInput};
+use crate::marathon::MarathonCurve;
+use crate::sound::SoundSettings;
+
+pub const APP_NAME: &str = "practris";
+
+/// The version of the settings schema. Bump it, and add a migration from the previous version to
+/// [`migrate`], whenever a change to [`UserSettings`] can't be read from older files.
+pub const SETTINGS_VERSION: u32 = 1;
+pub const DEFAULT_NAME: &str = "Player";
+/// The theme the window is drawn with unless another one is chosen.
+pub const DEFAULT_THEME: &str = "Default";
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UserSettings {
+    pub version: u32,
+    /// The name shown above the player's board, and to spectators.
+    pub name: String,
+    pub input: UserInput,
+    /// The rules games are played with.
+    pub game: GameConfig,
+    pub marathon: MarathonCurve,
+    pub sound: SoundSettings,
+    /// The name of the theme the window is drawn with.
+    pub theme: String,
+    pub accessibility: Accessibility,
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            name: DEFAULT_NAME.to_string(),
+            input: UserInput::default(),
+            game: GameConfig::fast_config(),
+            marathon: MarathonCurve::default(),
+            sound: SoundSettings::default(),
+            theme: DEFAULT_THEME.to_string(),
+            accessibility: Accessibility::default(),
+        }
+    }
+}
+
+/// Colours for pieces that are easier to tell apart with colour blindness than the skin's.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Palette {
+    /// The skin's own colours.
+    Skin,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl Palette {
+    /// The colour `cell` is drawn in instead of the skin's colour, if any.
+    pub fn color(self, cell: CellColor) -> Option<[u8; 3]> {
+        // indexed by piece: I, O, T, L, J, S, Z
+        let colors: [[u8; 3]; 7] = match self {
+            Palette::Skin => return None,
+            // Paul Tol's bright scheme
+            Palette::Protanopia => [
+                [102, 204, 238], [204, 187, 68], [170, 51, 119], [238, 102, 119],
+                [68, 119, 170], [34, 136, 51], [187, 187, 187],
+            ],
+            // Okabe and Ito's scheme
+            Palette::Deuteranopia => [
+                [86, 180, 233], [240, 228, 66], [204, 121, 167], [230, 159, 0],
+                [0, 114, 178], [0, 158, 115], [213, 94, 0],
+            ],
+            Palette::Tritanopia => [
+                [0, 170, 190], [255, 200, 210], [100, 40, 110], [235, 100, 40],
+                [30, 30, 60], [160, 160, 160], [200, 0, 40],
+            ],
+        };
+        let index = match cell {
+            CellColor::I => 0,
+            CellColor::O => 1,
+            CellColor::T => 2,
+            CellColor::L => 3,
+            CellColor::J => 4,
+            CellColor::S => 5,
+            CellColor::Z => 6,
+            _ => return None,
+        };
+        Some(colors[index])
+    }
+}
+
+/// Ways to tell minos apart other than by the skin's colours, which apply to any skin and theme.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Accessibility {
+    pub palette: Palette,
+    /// Draw the letter of its piece on every mino.
+    pub glyphs: bool,
+    /// Draw a dark outline around every mino, so neighbouring minos stand apart.
+    pub outlines: bool,
+    /// Draw the ghost piece with a bright outline, whatever the theme's ghost style.
+    pub visible_ghost: bool,
+}
+
+impl Default for Accessibility {
+    fn default() -> Self {
+        Self {
+            palette: Palette::Skin,
+            glyphs: false,
+            outlines: false,
+            visible_ghost: false,
+        }
+    }
+}
+
+/// Settings files from before settings were versioned.
+#[derive(Deserialize)]
+struct SettingsV0 {
+    input: InputV0,
+    #[serde(default)]
+    marathon: MarathonCurve,
+}
+
+#[derive(Deserialize)]
+struct InputV0 {
+    keyboard: ConfigV0<Key>,
+    gamepad: ConfigV0<GamepadControl>,
+}
+
+/// The bindings there were before settings were versioned.
+#[derive(Deserialize)]
+struct ConfigV0<T> {
+    left: T,
+    right: T,
+    rotate_left: T,
+    rotate_right: T,
+    rotate_180: T,
+    hard_drop: T,
+    soft_drop: T,
+    hold: T,
+    reset: T,
+}
+
+impl<T> ConfigV0<T> {
+    /// The same bindings, with the ones added since taken from `defaults`.
+    fn migrate(self, defaults: Config<T>) -> Config<T> {
+        Config {
+            left: self.left,
+            right: self.right,
+            rotate_left: self.rotate_left,
+            rotate_right: self.rotate_right,
+            rotate_180: self.rotate_180,
+            hard_drop: self.hard_drop,
+            soft_drop: self.soft_drop,
+            hold: self.hold,
+            reset: self.reset,
+            ..defaults
+        }
+    }
+}
+
+/// Just enough of a settings file to know its version. Files without one are version 0.
+#[derive(Deserialize)]
+struct Header {
+    #[serde(default)]
+    version: u32,
+}
+
+/// Read settings of any version, migrating them to the current one.
+fn migrate(src: &str) -> Result<UserSettings, String> {
+    let Header { version } = ron::from_str(src).map_err(|e| e.to_string())?;
+    match version {
+        0 => {
+            let v0: SettingsV0 = ron::from_str(src).map_err(|e| e.to_string())?;
+            Ok(UserSettings {
+                version: SETTINGS_VERSION,
+                name: DEFAULT_NAME.to_string(),
+                input: UserInput {
+                    keyboard: v0.input.keyboard.migrate(Config::default()),
+                    gamepad: v0.input.gamepad.migrate(Config::default()),
+                },
+                game: GameConfig::fast_config(),
+                marathon: v0.marathon,
+                sound: SoundSettings::default(),
+                theme: DEFAULT_THEME.to_string(),
+                accessibility: Accessibility::default(),
+            })
+        }
+        SETTINGS_VERSION => ron::from_str(src).map_err(|e| e.to_string()),
+        version => Err(format!("settings are from a newer version of practris (version {version})")),
+    }
+}
+
+pub fn settings_path(profile: Option<&str>) -> Option<PathBuf> {
+    let name = match profile {
+        Some(profile) => format!("{}-{profile}.ron", APP_NAME),
+        None => format!("{}.ron", APP_NAME),
+    };
+    dirs::config_dir().map(|d| d.join(name))
+}
+
+/// Read the settings at `path`, using default settings if they can't be read.
+pub fn load(path: &Path) -> UserSettings {
+    try_load(path).unwrap_or_else(|e| {
+        log::error!("Failed to read config: {e}");
+        UserSettings::default()
+    })
+}
+
+/// Read the settings at `path`, or default settings if there's no file there yet.
+///
+/// A file that can't be understood is moved aside to a backup rather than lost, and default
+/// settings are used instead. A file that can't be read at all is left alone, and is an error.
+fn try_load(path: &Path) -> Result<UserSettings, String> {
+    log::info!("Loading configuration from {path:?}");
+    let src = match std::fs::read(path) {
+        Ok(src) => src,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            log::info!("Configuration file not present: probably a first launch.");
+            return Ok(UserSettings::default());
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let settings = String::from_utf8(src)
+        .map_err(|e| e.to_string())
+        .and_then(|src| migrate(&src));
+    match settings {
+        Ok(settings) => Ok(settings),
+        Err(e) => {
+            log::error!("Failed to read config: {e}!");
+            let backup = backup_path(path);
+            match std::fs::rename(path, &backup) {
+                Ok(()) => log::info!("Moved the unreadable configuration to {backup:?}"),
+                Err(e) => return Err(format!("failed to back up the unreadable configuration: {e}")),
+            }
+            Ok(UserSettings::default())
+        }
+    }
+}
+
+/// Where to move an unreadable settings file at `path` to, without overwriting earlier backups.
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.with_extension("ron.bak");
+    let mut n = 1;
+    while backup.exists() {
+        backup = path.with_extension(format!("ron.bak.{n}"));
+        n += 1;
+    }
+    backup
+}
+
+/// Write `settings` to `path`, without leaving a half-written file behind if that fails.
+pub fn save(path: &Path, settings: &UserSettings) -> Result<(), String> {
+    let src = ron::ser::to_string_pretty(settings, Default::default()).map_err(|e| e.to_string())?;
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let temp = path.with_extension("ron.tmp");
+    let mut file = File::create(&temp).map_err(|e| e.to_string())?;
+    file.write_all(src.as_bytes()).map_err(|e| e.to_string())?;
+    // the contents have to be on disk before the rename is, or a crash could leave an empty file
+    file.sync_all().map_err(|e| e.to_string())?;
+    std::fs::rename(&temp, path).map_err(|e| e.to_string())?;
+    // and the rename itself only survives a crash once the directory is synced
+    #[cfg(unix)]
+    File::open(dir).and_then(|dir| dir.sync_all()).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// `ours` with the fields that didn't change since `base` taken from `theirs` instead, so that
+/// neither side's changes are lost.
+///
+/// Fields changed on both sides keep our change.
+fn merge(base: &UserSettings, ours: &UserSettings, theirs: UserSettings) -> UserSettings {
+    let mut merged = theirs;
+    macro_rules! keep_ours {
+        ($($field:ident),*) => {$(
+            if ours.$field != base.$field {
+                if merged.$field != base.$field {
+                    log::warn!(
+                        "The {} setting was also changed in the configuration file, keeping ours",
+                        stringify!($field),
+                    );
+                }
+                merged.$field = ours.$field.clone();
+            }
+        )*};
+    }
+    keep_ours!(name, input, game, marathon, sound, theme, accessibility);
+    merged
+}
+
+/// The file settings are kept in, which is saved as soon as settings change and read again when
+/// it's edited by something else.
+pub struct SettingsFile {
+    path: PathBuf,
+    /// The settings as they were last read or saved.
+    saved: UserSettings,
+    /// When the file was last read or saved.
+    modified: Option<SystemTime>,
+    /// Whether the file could be read, as otherwise saving would overwrite what's in it.
+    readable: bool,
+}
+
+impl SettingsFile {
+    pub fn open(path: PathBuf) -> Self {
+        let (saved, readable) = match try_load(&path) {
+            Ok(settings) => (settings, true),
+            Err(e) => {
+                log::error!("Failed to read config, changes to the settings won't be saved: {e}");
+                (UserSettings::default(), false)
+            }
+        };
+        let modified = modified(&path);
+        Self { path, saved, modified, readable }
+    }
+
+    pub fn settings(&self) -> &UserSettings {
+        &self.saved
+    }
+
+    /// Save `settings` if they changed since they were last saved.
+    ///
+    /// If the file was edited by something else in the meantime, those edits are merged into
+    /// `settings` first rather than overwritten.
+    pub fn save(&mut self, settings: &mut UserSettings) {
+        if !self.readable || *settings == self.saved {
+            return;
+        }
+        if let Some(edited) = self.read_if_modified() {
+            *settings = merge(&self.saved, settings, edited.clone());
+            self.saved = edited;
+            if *settings == self.saved {
+                return;
+            }
+        }
+        log::info!("Saving configuration to {:?}", self.path);
+        match save(&self.path, settings) {
+            Ok(()) => {
+                self.saved = settings.clone();
+                self.modified = modified(&self.path);
+            }
+            Err(e) => log::error!("Failed to write configuration: {e}"),
+        }
+    }
+
+    /// Merge edits made to the file by something else into `settings`, keeping the changes to
+    /// `settings` that weren't saved yet.
+    pub fn reload(&mut self, settings: &mut UserSettings) {
+        if let Some(edited) = self.read_if_modified() {
+            *settings = merge(&self.saved, settings, edited.clone());
+            self.saved = edited;
+            self.readable = true;
+        }
+    }
+
+    /// The settings in the file, if it was edited since it was last read or saved.
+    ///
+    /// Edits that can't be read are ignored, as the file might be in the middle of being edited.
+    fn read_if_modified(&mut self) -> Option<UserSettings> {
+        let modified = modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        let src = std::fs::read_to_string(&self.path).ok()?;
+        match migrate(&src) {
+            Ok(settings) => {
+                log::info!("Configuration file changed, reloaded it");
+                Some(settings)
+            }
+            Err(e) => {
+                log::error!("Failed to reload config: {e}");
+                None
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::marathon::LevelSpeed;
+
+    #[test]
+    fn migrates_unversioned_settings() {
+        // a settings file as practris wrote it before settings were versioned
+        let src = "(
+            input: (
+                keyboard: (
+                    left: A,
+                    right: D,
+                    rotate_left: Left,
+                    rotate_right: Right,
+                    rotate_180: Up,
+                    hard_drop: W,
+                    soft_drop: S,
+                    hold: LShift,
+                    reset: R,
+                ),
+                gamepad: (
+                    left: Button(DPadLeft),
+                    right: Button(DPadRight),
+                    rotate_left: Button(West),
+                    rotate_right: Button(East),
+                    rotate_180: Button(North),
+                    hard_drop: Button(DPadUp),
+                    soft_drop: Button(DPadDown),
+                    hold: Button(RightTrigger),
+                    reset: Button(Select),
+                ),
+            ),
+        )";
+
+        let settings = migrate(src).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        let (keyboard, gamepad) = (settings.input.keyboard, settings.input.gamepad);
+        assert_eq!((keyboard.left, keyboard.right, keyboard.hard_drop), (Key::A, Key::D, Key::W));
+        assert_eq!((keyboard.rotate_180, keyboard.hold), (Key::Up, Key::LShift));
+        assert_eq!(gamepad.rotate_left, GamepadControl::Button(gilrs::Button::West));
+        assert_eq!(gamepad.hold, GamepadControl::Button(gilrs::Button::RightTrigger));
+        assert_eq!(gamepad.reset, GamepadControl::Button(gilrs::Button::Select));
+
+        // bindings added since are the defaults
+        let defaults = UserInput::default();
+        assert_eq!(keyboard.undo, defaults.keyboard.undo);
+        assert_eq!(gamepad.hint, defaults.gamepad.hint);
+
+        assert_eq!(settings.marathon, MarathonCurve::default());
+        assert_eq!(settings.game, GameConfig::fast_config());
+        assert_eq!(settings.theme, DEFAULT_THEME);
+        assert_eq!(settings.name, DEFAULT_NAME);
+    }
+
+    #[test]
+    fn migrates_unversioned_marathon_curves() {
+        let marathon = MarathonCurve {
+            levels: vec![LevelSpeed { gravity: 100, lock_delay: 20, spawn_delay: 5 }],
+        };
+        let input = "(
+            keyboard: (left: Left, right: Right, rotate_left: Z, rotate_right: X, rotate_180: A,
+                hard_drop: Space, soft_drop: Down, hold: C, reset: R),
+            gamepad: (left: Button(DPadLeft), right: Button(DPadRight), rotate_left: Button(South),
+                rotate_right: Button(East), rotate_180: Button(North), hard_drop: Button(DPadUp),
+                soft_drop: Button(DPadDown), hold: Button(LeftTrigger), reset: Button(West)),
+        )";
+        let src = format!("(input: {input}, marathon: {})", ron::to_string(&marathon).unwrap());
+
+        let settings = migrate(&src).unwrap();
+        assert_eq!(settings.input, UserInput::default());
+        assert_eq!(settings.marathon, marathon);
+    }
+
+    #[test]
+    fn rejects_settings_from_newer_versions() {
+        let src = format!("(version: {})", SETTINGS_VERSION + 1);
+        assert!(migrate(&src).is_err());
+    }
+
+    #[test]
+    fn merging_keeps_changes_from_both_sides() {
+        let base = UserSettings::default();
+        let ours = UserSettings { name: "ours".to_string(), ..base.clone() };
+        let theirs = UserSettings { theme: "theirs".to_string(), ..base.clone() };
+
+        let merged = merge(&base, &ours, theirs);
+        assert_eq!(merged.name, "ours");
+        assert_eq!(merged.theme, "theirs");
+    }
+
+    /// An empty directory of its own for a test to keep files in.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("practris-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn unreadable_settings_are_backed_up_without_overwriting_older_backups() {
+        let dir = test_dir("backups");
+        let path = dir.join("practris.ron");
+
+        assert!(try_load(&path).unwrap() == UserSettings::default());
+
+        std::fs::write(&path, "first").unwrap();
+        assert!(try_load(&path).unwrap() == UserSettings::default());
+        std::fs::write(&path, [0xff, 0xfe]).unwrap();
+        assert!(try_load(&path).unwrap() == UserSettings::default());
+
+        assert!(!path.exists());
+        assert_eq!(std::fs::read_to_string(dir.join("practris.ron.bak")).unwrap(), "first");
+        assert_eq!(std::fs::read(dir.join("practris.ron.bak.1")).unwrap(), [0xff, 0xfe]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn settings_that_cant_be_read_are_not_saved_over() {
+        // a directory where the file should be can't be read, but isn't missing either
+        let dir = test_dir("unreadable");
+        let path = dir.join("practris.ron");
+        std::fs::create_dir(&path).unwrap();
+        assert!(try_load(&path).is_err());
+
+        let mut file = SettingsFile::open(path.clone());
+        let mut settings = UserSettings { name: "changed".to_string(), ..UserSettings::default() };
+        file.save(&mut settings);
+        assert!(path.is_dir());
+        assert!(file.settings() == &UserSettings::default());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,1178 @@
+use libtetris::*;
+use ordered_float::NotNan;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::attack::AttackTable;
+use crate::input::GameInput;
+use crate::rotation::{Kick, Rotation, RotationSystem};
+
+/// The gravity at which pieces fall 20 rows every tick, and thus drop to the stack as soon as they
+/// spawn.
+pub const TWENTY_G: i32 = 5;
+
+/// Rows above this are hidden, and pieces locking there can top out.
+const VISIBLE_ROWS: i32 = 20;
+
+/// Units are in ticks
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub spawn_delay: u32,
+    pub line_clear_delay: u32,
+    pub delayed_auto_shift: u32,
+    pub auto_repeat_rate: u32,
+    pub soft_drop_speed: u32,
+    pub lock_delay: u32,
+    /// Measured in 1/100 of a tick
+    pub gravity: i32,
+
+    pub next_queue_size: u32,
+    pub max_garbage_add: u32,
+    /// How many moves and rotations reset lock delay under [`LockReset::Move`].
+    pub move_lock_rule: u32,
+    pub lock_reset: LockReset,
+    /// How long a piece can be on the stack in total before it locks, however often its lock
+    /// delay was reset.
+    pub max_lock_time: Option<u32>,
+    pub garbage_blocking: bool,
+    pub garbage_messiness: NotNan<f64>,
+    pub attack: AttackTable,
+    /// Initial rotation: a rotation held down while a piece spawns is applied immediately.
+    pub irs: bool,
+    /// Initial hold: holding hold while a piece spawns holds it immediately.
+    pub ihs: bool,
+    pub rotation_system: RotationSystem,
+    /// The row pieces spawn on, counting from 1 at the bottom of the board.
+    pub spawn_row: u32,
+    /// Whether pieces move down a row as soon as they spawn, if there's room.
+    pub spawn_fall: bool,
+    pub top_out: TopOutRules,
+}
+
+/// What resets the lock delay of a piece on the stack.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LockReset {
+    /// Moving or rotating the piece, up to `move_lock_rule` times before it reaches a lower row.
+    Move,
+    /// Only reaching a lower row than the piece has been on before.
+    Step,
+    /// Moving or rotating the piece, any number of times.
+    Infinite,
+    /// Nothing; the piece locks once it has been on the stack for the lock delay.
+    None,
+}
+
+/// Which of the ways to top out end the game.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopOutRules {
+    /// A piece locks entirely above the visible board.
+    pub lock_out: bool,
+    /// A piece can't spawn because the stack is in the way. When this is off, pieces spawn higher
+    /// up instead, as long as they fit anywhere, and holding fails when the held piece can't.
+    pub block_out: bool,
+    /// Any part of a piece locks above the visible board.
+    pub partial_lock_out: bool,
+    /// Incoming garbage pushes the stack past the top of the board.
+    pub garbage_out: bool,
+}
+
+impl Default for TopOutRules {
+    fn default() -> Self {
+        TopOutRules {
+            lock_out: true,
+            block_out: true,
+            partial_lock_out: false,
+            garbage_out: true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameOverReason {
+    LockOut,
+    BlockOut,
+    PartialLockOut,
+    GarbageOut,
+}
+
+impl std::fmt::Display for GameOverReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            GameOverReason::LockOut => "Lock out",
+            GameOverReason::BlockOut => "Block out",
+            GameOverReason::PartialLockOut => "Partial lock out",
+            GameOverReason::GarbageOut => "Garbage out",
+        })
+    }
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        // Use something approximating Puyo Puyo Tetris
+        GameConfig {
+            spawn_delay: 7,
+            line_clear_delay: 35,
+            delayed_auto_shift: 9,
+            auto_repeat_rate: 2,
+            soft_drop_speed: 2,
+            lock_delay: 30,
+            gravity: 4500,
+            next_queue_size: 5,
+            max_garbage_add: 10,
+            move_lock_rule: 15,
+            lock_reset: LockReset::Move,
+            max_lock_time: None,
+            garbage_blocking: false,
+            garbage_messiness: NotNan::new(0.3).unwrap(),
+            attack: AttackTable::Ppt,
+            irs: false,
+            ihs: false,
+            rotation_system: RotationSystem::Srs,
+            spawn_row: 21,
+            spawn_fall: true,
+            top_out: TopOutRules::default(),
+        }
+    }
+}
+
+impl GameConfig {
+    pub fn fast_config() -> Self {
+        GameConfig {
+            spawn_delay: 0,
+            line_clear_delay: 0,
+            delayed_auto_shift: 8,
+            auto_repeat_rate: 0,
+            soft_drop_speed: 0,
+            lock_delay: 30,
+            gravity: 4500,
+            next_queue_size: 5,
+            max_garbage_add: 20,
+            move_lock_rule: 15,
+            lock_reset: LockReset::Move,
+            max_lock_time: None,
+            garbage_blocking: true,
+            garbage_messiness: NotNan::new(0.0).unwrap(),
+            attack: AttackTable::Ppt,
+            irs: false,
+            ihs: false,
+            rotation_system: RotationSystem::Srs,
+            spawn_row: 21,
+            spawn_fall: true,
+            top_out: TopOutRules::default(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Game {
+    pub board: Board<ColoredRow>,
+    state: GameState,
+    config: GameConfig,
+    did_hold: bool,
+    prev: Controller,
+    used: Controller,
+    left_das: u32,
+    right_das: u32,
+    going_right: bool,
+    pub garbage_queue: u32,
+    pub attacking: u32,
+    /// How many difficult clears were made in a row, for attack tables that reward long
+    /// back-to-back chains.
+    #[serde(default)]
+    b2b_chain: u32,
+    #[serde(default)]
+    last_kick: Option<Kick>,
+    /// How many ticks the game has been updated for.
+    #[serde(default)]
+    ticks: u32,
+    /// Buttons pressed since the last tick, which count even if they were released again.
+    #[serde(skip)]
+    tapped: Controller,
+    /// The buttons in `tapped`, in the order they were pressed.
+    #[serde(skip)]
+    press_order: Vec<GameInput>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Event {
+    PieceSpawned {
+        new_in_queue: Piece,
+    },
+    SpawnDelayStart,
+    FrameBeforePieceSpawns,
+    PieceMoved,
+    PieceRotated,
+    PieceTSpun,
+    PieceHeld(Piece),
+    StackTouched,
+    SoftDropped,
+    PieceFalling(FallingPiece, FallingPiece),
+    EndOfLineClearDelay,
+    PiecePlaced {
+        piece: FallingPiece,
+        locked: LockResult,
+        hard_drop_distance: Option<i32>,
+    },
+    GarbageSent(u32),
+    GarbageAdded(Vec<usize>),
+    GameOver(GameOverReason),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+enum GameState {
+    SpawnDelay(u32),
+    LineClearDelay(u32),
+    Falling(FallingState),
+    GameOver(GameOverReason),
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+struct FallingState {
+    piece: FallingPiece,
+    lowest_y: i32,
+    rotation_move_count: u32,
+    gravity: i32,
+    lock_delay: u32,
+    soft_drop_delay: u32,
+    /// How long the piece has been on the stack, for [`GameConfig::max_lock_time`].
+    #[serde(default)]
+    lock_time: u32,
+}
+
+impl FallingState {
+    /// The piece was moved or rotated.
+    fn moved(&mut self, config: &GameConfig) {
+        self.rotation_move_count += 1;
+        if let LockReset::Move | LockReset::Infinite = config.lock_reset {
+            self.lock_delay = config.lock_delay;
+        }
+    }
+}
+
+impl Game {
+    pub fn new(config: GameConfig, piece_rng: &mut impl Rng) -> Self {
+        let mut board = Board::new();
+        for _ in 0..config.next_queue_size {
+            board.add_next_piece(board.generate_next_piece(piece_rng));
+        }
+        Self::with_board(config, board)
+    }
+
+    /// Start from a position rather than an empty board. The queue is filled up with random pieces
+    /// after `queue`.
+    pub fn from_position(
+        config: GameConfig,
+        field: [[bool; 10]; 40],
+        hold: Option<Piece>,
+        queue: &[Piece],
+        piece_rng: &mut impl Rng,
+    ) -> Self {
+        let bag = Board::<ColoredRow>::new().bag;
+        let mut board = Board::new_with_state(field, bag, hold, false, 0);
+        for &piece in queue {
+            board.add_next_piece(piece);
+        }
+        while board.next_queue().count() < config.next_queue_size as usize {
+            board.add_next_piece(board.generate_next_piece(piece_rng));
+        }
+        Self::with_board(config, board)
+    }
+
+    fn with_board(config: GameConfig, board: Board<ColoredRow>) -> Self {
+        Game {
+            board,
+            config,
+            prev: Default::default(),
+            used: Default::default(),
+            did_hold: false,
+            left_das: config.delayed_auto_shift,
+            right_das: config.delayed_auto_shift,
+            going_right: false,
+            state: GameState::SpawnDelay(config.spawn_delay),
+            garbage_queue: 0,
+            attacking: 0,
+            b2b_chain: 0,
+            last_kick: None,
+            ticks: 0,
+            tapped: Controller::default(),
+            press_order: vec![],
+        }
+    }
+
+    /// The piece currently under player control, if any.
+    pub fn falling_piece(&self) -> Option<FallingPiece> {
+        match self.state {
+            GameState::Falling(falling) => Some(falling.piece),
+            _ => None,
+        }
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.game_over_reason().is_some()
+    }
+
+    pub fn game_over_reason(&self) -> Option<GameOverReason> {
+        match self.state {
+            GameState::GameOver(reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// How many ticks the game has been updated for, which garbage schedules and attack timelines
+    /// are timed by.
+    pub fn ticks(&self) -> u32 {
+        self.ticks
+    }
+
+    /// The most recent successful rotation, and which kick it used.
+    pub fn last_kick(&self) -> Option<Kick> {
+        self.last_kick
+    }
+
+    pub fn config(&self) -> &GameConfig {
+        &self.config
+    }
+
+    /// Change the rules of the game as it's being played, such as its speed.
+    pub fn set_config(&mut self, config: GameConfig) {
+        self.config = config;
+    }
+
+    /// Like [`Game::update`], also given the buttons that were pressed since the last tick, in the
+    /// order they were pressed in.
+    ///
+    /// Buttons that were pressed and released again between two ticks still register, and the
+    /// piece is moved and rotated in the order the buttons were pressed.
+    pub fn update_with_presses(
+        &mut self,
+        current: Controller,
+        presses: &[GameInput],
+        piece_rng: &mut impl Rng,
+        garbage_rng: &mut impl Rng,
+    ) -> Vec<Event> {
+        self.tapped = Controller::default();
+        for &input in presses {
+            input.set(&mut self.tapped, true);
+        }
+        self.press_order = presses.to_vec();
+        let events = self.update(current, piece_rng, garbage_rng);
+        self.tapped = Controller::default();
+        self.press_order.clear();
+        events
+    }
+
+    pub fn update(
+        &mut self,
+        current: Controller,
+        piece_rng: &mut impl Rng,
+        garbage_rng: &mut impl Rng,
+    ) -> Vec<Event> {
+        self.ticks += 1;
+        let tapped = self.tapped;
+        update_input(&mut self.used.left, self.prev.left, current.left, tapped.left);
+        update_input(&mut self.used.right, self.prev.right, current.right, tapped.right);
+        update_input(
+            &mut self.used.rotate_right,
+            self.prev.rotate_right,
+            current.rotate_right,
+            tapped.rotate_right,
+        );
+        update_input(
+            &mut self.used.rotate_left,
+            self.prev.rotate_left,
+            current.rotate_left,
+            tapped.rotate_left,
+        );
+        update_input(
+            &mut self.used.rotate_180,
+            self.prev.rotate_180,
+            current.rotate_180,
+            tapped.rotate_180,
+        );
+        update_input(&mut self.used.hold, self.prev.hold, current.hold, tapped.hold);
+        self.used.hard_drop = !self.prev.hard_drop && current.hard_drop || tapped.hard_drop;
+        self.used.soft_drop = current.soft_drop || tapped.soft_drop;
+
+        if !self.prev.left && current.left || tapped.left && !tapped.right {
+            self.going_right = false;
+            self.used.right = false;
+        } else if !self.prev.right && current.right || tapped.right {
+            self.going_right = true;
+            self.used.left = false;
+        }
+
+        if current.left {
+            if self.used.left || current.right && self.going_right {
+                if self.left_das > self.config.auto_repeat_rate {
+                    self.left_das -= 1;
+                } else {
+                    self.left_das = self.config.auto_repeat_rate;
+                }
+            } else {
+                if self.left_das != 0 {
+                    self.left_das -= 1;
+                }
+                if self.left_das == 0 {
+                    self.used.left = true;
+                    self.left_das = self.config.auto_repeat_rate;
+                }
+            }
+        } else {
+            self.left_das = self.config.delayed_auto_shift;
+        }
+
+        if current.right {
+            if self.used.right || current.left && !self.going_right {
+                if self.right_das > self.config.auto_repeat_rate {
+                    self.right_das -= 1;
+                } else {
+                    self.right_das = self.config.auto_repeat_rate;
+                }
+            } else {
+                if self.right_das != 0 {
+                    self.right_das -= 1;
+                }
+                if self.right_das == 0 {
+                    self.used.right = true;
+                    self.right_das = self.config.auto_repeat_rate;
+                }
+            }
+        } else {
+            self.right_das = self.config.delayed_auto_shift;
+        }
+
+        self.prev = current;
+
+        match self.state {
+            GameState::SpawnDelay(0) => {
+                let mut events = vec![];
+                if self.config.spawn_delay == 0 {
+                    events.push(Event::FrameBeforePieceSpawns);
+                }
+                let new_piece = self.board.generate_next_piece(piece_rng);
+                self.board.add_next_piece(new_piece);
+                let mut next_piece = self.board.advance_queue().unwrap();
+                let mut new_pieces = vec![new_piece];
+
+                // Initial hold: holding hold during the spawn delay holds the piece before it spawns
+                let mut held = None;
+                if self.config.ihs && current.hold && !self.did_hold {
+                    self.did_hold = true;
+                    self.used.hold = false;
+                    held = Some(next_piece);
+                    next_piece = match self.board.hold(next_piece) {
+                        Some(piece) => piece,
+                        None => {
+                            let new_piece = self.board.generate_next_piece(piece_rng);
+                            self.board.add_next_piece(new_piece);
+                            new_pieces.push(new_piece);
+                            self.board.advance_queue().unwrap()
+                        }
+                    };
+                }
+
+                // Initial rotation: the piece spawns rotated if a rotation is held down
+                let rotation = if !self.config.irs {
+                    None
+                } else if current.rotate_right {
+                    Some(RotationState::East)
+                } else if current.rotate_left {
+                    Some(RotationState::West)
+                } else if current.rotate_180 {
+                    Some(RotationState::South)
+                } else {
+                    None
+                };
+
+                // at 20G, the piece is already on the stack when it spawns
+                if let Some(falling) = spawn(&self.config, &self.board, next_piece, rotation) {
+                    if falling.piece.kind.1 != RotationState::North {
+                        self.used.rotate_right = false;
+                        self.used.rotate_left = false;
+                        self.used.rotate_180 = false;
+                    }
+                    let spawned = falling.piece;
+                    self.state = GameState::Falling(falling);
+                    let mut ghost = spawned;
+                    ghost.sonic_drop(&self.board);
+                    for new_in_queue in new_pieces {
+                        events.push(Event::PieceSpawned { new_in_queue });
+                    }
+                    if let Some(held) = held {
+                        events.push(Event::PieceHeld(held));
+                    }
+                    events.push(Event::PieceFalling(spawned, ghost));
+                } else {
+                    self.state = GameState::GameOver(GameOverReason::BlockOut);
+                    events.push(Event::GameOver(GameOverReason::BlockOut));
+                }
+                events
+            }
+            GameState::SpawnDelay(ref mut delay) => {
+                *delay -= 1;
+                let mut events = vec![];
+                if *delay == 0 {
+                    events.push(Event::FrameBeforePieceSpawns);
+                }
+                if *delay + 1 == self.config.spawn_delay {
+                    events.push(Event::SpawnDelayStart);
+                }
+                events
+            }
+            GameState::LineClearDelay(0) => {
+                self.state = GameState::SpawnDelay(self.config.spawn_delay);
+                let mut events = vec![Event::EndOfLineClearDelay];
+                if !self.config.garbage_blocking {
+                    self.deal_garbage(&mut events, garbage_rng);
+                }
+                events
+            }
+            GameState::LineClearDelay(ref mut delay) => {
+                *delay -= 1;
+                vec![]
+            }
+            GameState::GameOver(reason) => vec![Event::GameOver(reason)],
+            GameState::Falling(ref mut falling) => {
+                let mut events = vec![];
+                let was_on_stack = self.board.on_stack(&falling.piece);
+
+                // Hold
+                if !self.did_hold && self.used.hold {
+                    self.did_hold = true;
+                    let current = falling.piece.kind.0;
+                    if let Some(piece) = self.board.hold(current) {
+                        // Piece in hold; the piece spawns instantly
+                        match spawn(&self.config, &self.board, piece, None) {
+                            Some(spawned) => {
+                                *falling = spawned;
+                                let spawned = spawned.piece;
+                                let mut ghost = spawned;
+                                ghost.sonic_drop(&self.board);
+                                events.push(Event::PieceHeld(current));
+                                events.push(Event::PieceFalling(spawned, ghost));
+                            }
+                            // Without block out, the hold fails instead, as the current piece can
+                            // still be played
+                            None if !self.config.top_out.block_out => {
+                                self.board.hold(piece);
+                            }
+                            None => {
+                                events.push(Event::PieceHeld(current));
+                                self.state = GameState::GameOver(GameOverReason::BlockOut);
+                                events.push(Event::GameOver(GameOverReason::BlockOut));
+                            }
+                        }
+                    } else {
+                        // Nothing in hold; spawn next piece normally
+                        events.push(Event::PieceHeld(current));
+                        self.state = GameState::SpawnDelay(self.config.spawn_delay);
+                    }
+                    return events;
+                }
+
+                // Rotate and shift, in the order the buttons were pressed in
+                let order = self.press_order.iter()
+                    .chain(&[GameInput::RotateRight, GameInput::RotateLeft, GameInput::Rotate180])
+                    .chain(&[GameInput::Left, GameInput::Right]);
+                let mut done = vec![];
+                for &input in order {
+                    if done.contains(&input) {
+                        continue;
+                    }
+                    done.push(input);
+                    match input {
+                        GameInput::RotateRight | GameInput::RotateLeft | GameInput::Rotate180 => {
+                            let (used, rotation) = match input {
+                                GameInput::RotateRight => (&mut self.used.rotate_right, Rotation::Cw),
+                                GameInput::RotateLeft => (&mut self.used.rotate_left, Rotation::Ccw),
+                                _ => (&mut self.used.rotate_180, Rotation::Half),
+                            };
+                            if !*used {
+                                continue;
+                            }
+                            let system = self.config.rotation_system;
+                            let Some(kick) = system.rotate(&self.board, &mut falling.piece, rotation) else {
+                                continue;
+                            };
+                            *used = false;
+                            self.last_kick = Some(kick);
+                            falling.moved(&self.config);
+                            if falling.piece.tspin != TspinStatus::None {
+                                events.push(Event::PieceTSpun);
+                            } else {
+                                events.push(Event::PieceRotated);
+                            }
+                        }
+                        GameInput::Left => {
+                            while self.used.left && falling.piece.shift(&self.board, -1, 0) {
+                                self.used.left = self.config.auto_repeat_rate == 0 && self.left_das == 0;
+                                falling.moved(&self.config);
+                                events.push(Event::PieceMoved);
+                            }
+                        }
+                        GameInput::Right => {
+                            while self.used.right && falling.piece.shift(&self.board, 1, 0) {
+                                self.used.right = self.config.auto_repeat_rate == 0 && self.right_das == 0;
+                                falling.moved(&self.config);
+                                events.push(Event::PieceMoved);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                // 15 move lock rule reset
+                let low_y = falling.piece.cells().iter().map(|&(_, y)| y).min().unwrap();
+                if low_y < falling.lowest_y {
+                    falling.rotation_move_count = 0;
+                    falling.lowest_y = low_y;
+                    if self.config.lock_reset == LockReset::Step {
+                        falling.lock_delay = self.config.lock_delay;
+                    }
+                }
+
+                // 15 move lock rule
+                if self.config.lock_reset == LockReset::Move
+                    && falling.rotation_move_count >= self.config.move_lock_rule {
+                    let mut p = falling.piece;
+                    p.sonic_drop(&self.board);
+                    let low_y = p.cells().iter().map(|&(_, y)| y).min().unwrap();
+                    // I don't think the 15 move lock rule applies if the piece can fall to a lower
+                    // y position than it has ever reached before.
+                    if low_y >= falling.lowest_y {
+                        let mut f = *falling;
+                        f.piece = p;
+                        self.lock(f, &mut events, garbage_rng, None);
+                        return events;
+                    }
+                }
+
+                // Hard drop
+                if self.used.hard_drop {
+                    let y = falling.piece.y;
+                    falling.piece.sonic_drop(&self.board);
+                    let distance = y - falling.piece.y;
+                    let f = *falling;
+                    self.lock(f, &mut events, garbage_rng, Some(distance));
+                    return events;
+                }
+
+                if self.board.on_stack(&falling.piece) {
+                    // Lock delay
+                    if !was_on_stack {
+                        events.push(Event::StackTouched);
+                    }
+                    falling.lock_delay = falling.lock_delay.saturating_sub(1);
+                    falling.lock_time += 1;
+                    falling.gravity = self.config.gravity;
+                    let out_of_time = self.config.max_lock_time
+                        .map_or(false, |max| falling.lock_time >= max);
+                    if falling.lock_delay == 0 || out_of_time {
+                        let f = *falling;
+                        self.lock(f, &mut events, garbage_rng, None);
+                        return events;
+                    }
+                } else {
+                    // Gravity
+                    if let LockReset::Move | LockReset::Infinite = self.config.lock_reset {
+                        falling.lock_delay = self.config.lock_delay;
+                    }
+                    falling.gravity -= 100;
+                    while falling.gravity < 0 {
+                        falling.gravity += self.config.gravity;
+                        falling.piece.shift(&self.board, 0, -1);
+                    }
+
+                    if self.board.on_stack(&falling.piece) {
+                        events.push(Event::StackTouched);
+                    } else if self.config.gravity > self.config.soft_drop_speed as i32 * 100 {
+                        // Soft drop
+                        if self.used.soft_drop {
+                            while falling.soft_drop_delay == 0 {
+                                falling.piece.shift(&self.board, 0, -1);
+                                falling.soft_drop_delay = self.config.soft_drop_speed;
+                                falling.gravity = self.config.gravity;
+                                events.push(Event::PieceMoved);
+                                events.push(Event::SoftDropped);
+                                if self.board.on_stack(&falling.piece) {
+                                    events.push(Event::StackTouched);
+                                    break;
+                                }
+                            }
+                            if falling.soft_drop_delay != 0 {
+                                falling.soft_drop_delay -= 1;
+                            }
+                        } else {
+                            falling.soft_drop_delay = 0;
+                        }
+                    }
+                }
+
+                let mut ghost = falling.piece;
+                ghost.sonic_drop(&self.board);
+                events.push(Event::PieceFalling(falling.piece, ghost));
+
+                events
+            }
+        }
+    }
+
+    fn lock(
+        &mut self,
+        falling: FallingState,
+        events: &mut Vec<Event>,
+        garbage_rng: &mut impl Rng,
+        dist: Option<i32>,
+    ) {
+        self.did_hold = false;
+        let mut locked = self.board.lock_piece(falling.piece);
+        if locked.placement_kind.is_clear() {
+            if locked.placement_kind.is_hard() {
+                self.b2b_chain += 1;
+            } else {
+                self.b2b_chain = 0;
+            }
+        }
+        locked.garbage_sent = self.config.attack.attack(&locked, self.b2b_chain);
+
+        events.push(Event::PiecePlaced {
+            piece: falling.piece,
+            locked: locked.clone(),
+            hard_drop_distance: dist,
+        });
+
+        let rules = self.config.top_out;
+        let above = falling.piece.cells().iter().filter(|&&(_, y)| y >= VISIBLE_ROWS).count();
+        let top_out = if rules.lock_out && above == 4 {
+            Some(GameOverReason::LockOut)
+        } else if rules.partial_lock_out && above > 0 {
+            Some(GameOverReason::PartialLockOut)
+        } else {
+            None
+        };
+
+        if let Some(reason) = top_out {
+            self.state = GameState::GameOver(reason);
+            events.push(Event::GameOver(reason));
+        } else if locked.cleared_lines.is_empty() {
+            self.state = GameState::SpawnDelay(self.config.spawn_delay);
+            self.deal_garbage(events, garbage_rng);
+        } else {
+            self.attacking += locked.garbage_sent;
+            self.state = GameState::LineClearDelay(self.config.line_clear_delay);
+        }
+    }
+
+    fn deal_garbage(&mut self, events: &mut Vec<Event>, rng: &mut impl Rng) {
+        if self.attacking > self.garbage_queue {
+            self.attacking -= self.garbage_queue;
+            self.garbage_queue = 0;
+        } else {
+            self.garbage_queue -= self.attacking;
+            self.attacking = 0;
+        }
+        if self.garbage_queue > 0 {
+            let mut dead = false;
+            let mut col = rng.gen_range(0, 10);
+            let mut garbage_columns = vec![];
+            for _ in 0..self.garbage_queue.min(self.config.max_garbage_add) {
+                if rng.gen_bool(self.config.garbage_messiness.into_inner()) {
+                    col = rng.gen_range(0, 10);
+                }
+                garbage_columns.push(col);
+                dead |= self.board.add_garbage(col);
+            }
+            self.garbage_queue -= self.garbage_queue.min(self.config.max_garbage_add);
+            events.push(Event::GarbageAdded(garbage_columns));
+            if dead && self.config.top_out.garbage_out {
+                events.push(Event::GameOver(GameOverReason::GarbageOut));
+                self.state = GameState::GameOver(GameOverReason::GarbageOut);
+            }
+        } else if self.attacking > 0 {
+            events.push(Event::GarbageSent(self.attacking));
+            self.attacking = 0;
+        }
+    }
+}
+
+/// Spawn `piece`, in the given rotation if there is one and it fits.
+///
+/// A rotated piece can still spawn when the unrotated one is blocked.
+fn spawn(
+    config: &GameConfig,
+    board: &Board<ColoredRow>,
+    piece: Piece,
+    rotation: Option<RotationState>,
+) -> Option<FallingState> {
+    let at = |kind, y| FallingPiece { kind, x: 4, y, tspin: TspinStatus::None };
+    let kinds = rotation.map(|rotation| PieceState(piece, rotation))
+        .into_iter()
+        .chain([PieceState(piece, RotationState::North)]);
+
+    let mut spawned = None;
+    for kind in kinds {
+        let mut y = config.spawn_row as i32 - 1;
+        // without block out, pieces move up until they fit
+        while !config.top_out.block_out && y < 39 && board.obstructed(&at(kind, y)) {
+            y += 1;
+        }
+        if !board.obstructed(&at(kind, y)) {
+            spawned = Some(at(kind, y));
+            break;
+        }
+    }
+    let mut spawned = spawned?;
+
+    if config.spawn_fall {
+        spawned.shift(board, 0, -1);
+    }
+    if config.gravity <= TWENTY_G {
+        spawned.sonic_drop(board);
+    }
+    Some(FallingState {
+        piece: spawned,
+        lowest_y: spawned.cells().iter().map(|&(_, y)| y).min().unwrap(),
+        rotation_move_count: 0,
+        gravity: config.gravity,
+        lock_delay: config.lock_delay,
+        soft_drop_delay: 0,
+        lock_time: 0,
+    })
+}
+
+fn update_input(used: &mut bool, prev: bool, current: bool, tapped: bool) {
+    if tapped {
+        *used = true;
+    } else if !current {
+        *used = false
+    } else if !prev {
+        *used = true;
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerUpdate {
+    pub events: Vec<Event>,
+    pub garbage_queue: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_pcg::Pcg64Mcg;
+
+    use super::*;
+
+    /// A game and the random number generators it's updated with.
+    struct TestGame {
+        game: Game,
+        piece_rng: Pcg64Mcg,
+        garbage_rng: Pcg64Mcg,
+    }
+
+    impl TestGame {
+        /// A game on `field` with `queue` coming up first. The first piece spawns on the first
+        /// update, or once the spawn delay is over.
+        fn new(config: GameConfig, field: [[bool; 10]; 40], hold: Option<Piece>, queue: &[Piece]) -> Self {
+            let mut piece_rng = Pcg64Mcg::from_seed([1; 16]);
+            Self {
+                game: Game::from_position(config, field, hold, queue, &mut piece_rng),
+                piece_rng,
+                garbage_rng: Pcg64Mcg::from_seed([2; 16]),
+            }
+        }
+
+        /// Update the game with `held` held down.
+        fn tick(&mut self, held: &[GameInput]) -> Vec<Event> {
+            let mut controller = Controller::default();
+            for &input in held {
+                input.set(&mut controller, true);
+            }
+            self.game.update(controller, &mut self.piece_rng, &mut self.garbage_rng)
+        }
+
+        /// Update the game with `presses` pressed and released again since the last update.
+        fn tap(&mut self, presses: &[GameInput]) -> Vec<Event> {
+            self.game.update_with_presses(Controller::default(), presses, &mut self.piece_rng, &mut self.garbage_rng)
+        }
+
+        fn piece(&self) -> FallingPiece {
+            self.game.falling_piece().expect("no piece is falling")
+        }
+    }
+
+    fn placed(events: &[Event]) -> bool {
+        events.iter().any(|e| matches!(e, Event::PiecePlaced { .. }))
+    }
+
+    /// A game that buffers inputs for three ticks before the first piece spawns.
+    fn delayed(irs: bool, ihs: bool) -> TestGame {
+        let config = GameConfig { spawn_delay: 3, irs, ihs, ..GameConfig::fast_config() };
+        TestGame::new(config, [[false; 10]; 40], None, &[Piece::T, Piece::L])
+    }
+
+    /// Hold `held` down until the first piece spawns, returning the events of the tick it did.
+    fn spawn_holding(game: &mut TestGame, held: &[GameInput]) -> Vec<Event> {
+        for _ in 0..3 {
+            game.tick(held);
+            assert!(game.game.falling_piece().is_none());
+        }
+        game.tick(held)
+    }
+
+    #[test]
+    fn initial_rotation_spawns_the_piece_rotated() {
+        let mut game = delayed(true, false);
+        spawn_holding(&mut game, &[GameInput::RotateRight]);
+        assert_eq!(game.piece().kind, PieceState(Piece::T, RotationState::East));
+
+        // the rotation was used up by spawning rotated
+        game.tick(&[GameInput::RotateRight]);
+        assert_eq!(game.piece().kind.1, RotationState::East);
+
+        let mut game = delayed(true, false);
+        spawn_holding(&mut game, &[GameInput::Rotate180]);
+        assert_eq!(game.piece().kind.1, RotationState::South);
+    }
+
+    #[test]
+    fn without_initial_rotation_the_piece_spawns_unrotated() {
+        let mut game = delayed(false, false);
+        spawn_holding(&mut game, &[GameInput::RotateRight]);
+        assert_eq!(game.piece().kind, PieceState(Piece::T, RotationState::North));
+    }
+
+    #[test]
+    fn initial_hold_holds_the_piece_before_it_spawns() {
+        let mut game = delayed(false, true);
+        let events = spawn_holding(&mut game, &[GameInput::Hold]);
+        assert!(events.iter().any(|e| matches!(e, Event::PieceHeld(Piece::T))));
+        assert_eq!(game.piece().kind.0, Piece::L);
+        assert_eq!(game.game.board.hold_piece, Some(Piece::T));
+
+        // the hold was used up, and holding again needs another piece to be placed first
+        game.tick(&[]);
+        game.tick(&[GameInput::Hold]);
+        assert_eq!(game.piece().kind.0, Piece::L);
+        assert_eq!(game.game.board.hold_piece, Some(Piece::T));
+    }
+
+    #[test]
+    fn initial_hold_and_rotation_together_rotate_the_piece_from_the_queue() {
+        let mut game = delayed(true, true);
+        spawn_holding(&mut game, &[GameInput::Hold, GameInput::RotateLeft]);
+        assert_eq!(game.piece().kind, PieceState(Piece::L, RotationState::West));
+        assert_eq!(game.game.board.hold_piece, Some(Piece::T));
+    }
+
+    #[test]
+    fn without_initial_hold_the_first_piece_spawns() {
+        let mut game = delayed(false, false);
+        spawn_holding(&mut game, &[GameInput::Hold]);
+        assert_eq!(game.piece().kind.0, Piece::T);
+        assert_eq!(game.game.board.hold_piece, None);
+    }
+
+    /// A field with `column` filled from the floor up to, not including, row `height`.
+    fn filled_column(column: usize, height: usize) -> [[bool; 10]; 40] {
+        let mut field = [[false; 10]; 40];
+        for row in &mut field[..height] {
+            row[column] = true;
+        }
+        field
+    }
+
+    fn with_top_out(rules: TopOutRules) -> GameConfig {
+        GameConfig { top_out: rules, ..GameConfig::fast_config() }
+    }
+
+    #[test]
+    fn block_out_when_the_piece_cant_spawn() {
+        // the stack reaches into the row the T spawns in
+        let field = filled_column(4, 22);
+        let mut game = TestGame::new(with_top_out(TopOutRules::default()), field, None, &[Piece::T]);
+        game.tick(&[]);
+        assert_eq!(game.game.game_over_reason(), Some(GameOverReason::BlockOut));
+
+        // without block out the piece spawns above the stack instead
+        let rules = TopOutRules { block_out: false, ..TopOutRules::default() };
+        let mut game = TestGame::new(with_top_out(rules), field, None, &[Piece::T]);
+        game.tick(&[]);
+        assert_eq!(game.game.game_over_reason(), None);
+        assert_eq!(game.piece().y, 22);
+    }
+
+    #[test]
+    fn lock_out_when_the_piece_locks_above_the_board() {
+        let field = filled_column(4, 22);
+        let rules = TopOutRules { block_out: false, ..TopOutRules::default() };
+        let mut game = TestGame::new(with_top_out(rules), field, None, &[Piece::T]);
+        game.tick(&[]);
+        game.tick(&[GameInput::HardDrop]);
+        assert_eq!(game.game.game_over_reason(), Some(GameOverReason::LockOut));
+
+        let rules = TopOutRules { block_out: false, lock_out: false, ..TopOutRules::default() };
+        let mut game = TestGame::new(with_top_out(rules), field, None, &[Piece::T]);
+        game.tick(&[]);
+        assert!(placed(&game.tick(&[GameInput::HardDrop])));
+        assert_eq!(game.game.game_over_reason(), None);
+    }
+
+    #[test]
+    fn partial_lock_out_when_part_of_the_piece_locks_above_the_board() {
+        // the T lands with its top cell above the visible board
+        let field = filled_column(4, 19);
+        let rules = TopOutRules { partial_lock_out: true, ..TopOutRules::default() };
+        let mut game = TestGame::new(with_top_out(rules), field, None, &[Piece::T]);
+        game.tick(&[]);
+        game.tick(&[GameInput::HardDrop]);
+        assert_eq!(game.game.game_over_reason(), Some(GameOverReason::PartialLockOut));
+
+        let mut game = TestGame::new(with_top_out(TopOutRules::default()), field, None, &[Piece::T]);
+        game.tick(&[]);
+        assert!(placed(&game.tick(&[GameInput::HardDrop])));
+        assert_eq!(game.game.game_over_reason(), None);
+    }
+
+    #[test]
+    fn garbage_out_when_garbage_pushes_the_stack_off_the_board() {
+        let field = filled_column(0, 30);
+        for (garbage_out, reason) in [(true, Some(GameOverReason::GarbageOut)), (false, None)] {
+            let rules = TopOutRules { garbage_out, ..TopOutRules::default() };
+            let mut game = TestGame::new(with_top_out(rules), field, None, &[Piece::T]);
+            game.tick(&[]);
+            game.game.garbage_queue = 20;
+            let events = game.tick(&[GameInput::HardDrop]);
+            assert!(events.iter().any(|e| matches!(e, Event::GarbageAdded(_))));
+            assert_eq!(game.game.game_over_reason(), reason);
+        }
+    }
+
+    #[test]
+    fn holding_a_piece_that_cant_spawn() {
+        // there's room for the T, but not for the held I
+        let field = filled_column(6, 40);
+        let mut game = TestGame::new(with_top_out(TopOutRules::default()), field, Some(Piece::I), &[Piece::T]);
+        game.tick(&[]);
+        game.tick(&[GameInput::Hold]);
+        assert_eq!(game.game.game_over_reason(), Some(GameOverReason::BlockOut));
+
+        // without block out the hold fails, and the T can still be played
+        let rules = TopOutRules { block_out: false, ..TopOutRules::default() };
+        let mut game = TestGame::new(with_top_out(rules), field, Some(Piece::I), &[Piece::T]);
+        game.tick(&[]);
+        game.tick(&[GameInput::Hold]);
+        assert_eq!(game.game.game_over_reason(), None);
+        assert_eq!(game.piece().kind.0, Piece::T);
+        assert_eq!(game.game.board.hold_piece, Some(Piece::I));
+        assert!(placed(&game.tick(&[GameInput::HardDrop])));
+    }
+
+    /// A game at 20G, so the T is on the stack as soon as it spawns.
+    fn on_stack(lock_reset: LockReset, field: [[bool; 10]; 40]) -> TestGame {
+        let config = GameConfig {
+            gravity: TWENTY_G,
+            lock_delay: 10,
+            lock_reset,
+            ..GameConfig::fast_config()
+        };
+        let mut game = TestGame::new(config, field, None, &[Piece::T]);
+        game.tick(&[]);
+        game
+    }
+
+    /// Move the piece back and forth `n` times, returning whether it was placed on the last move
+    /// and not before.
+    fn shuffle(game: &mut TestGame, n: u32) -> bool {
+        for i in 1..=n {
+            let direction = if i % 2 == 0 { GameInput::Right } else { GameInput::Left };
+            if placed(&game.tap(&[direction])) {
+                return i == n;
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn move_reset_locks_after_the_move_limit() {
+        let mut game = on_stack(LockReset::Move, [[false; 10]; 40]);
+        assert_eq!(game.game.config().move_lock_rule, 15);
+        assert!(shuffle(&mut game, 15));
+    }
+
+    #[test]
+    fn step_reset_ignores_moves_on_the_same_row() {
+        let mut game = on_stack(LockReset::Step, [[false; 10]; 40]);
+        assert!(shuffle(&mut game, 10));
+    }
+
+    #[test]
+    fn step_reset_resets_on_a_new_lowest_row() {
+        // a ledge two rows high the T can be moved off of
+        let mut field = [[false; 10]; 40];
+        for row in &mut field[..2] {
+            row[..7].fill(true);
+        }
+        let mut game = on_stack(LockReset::Step, field);
+        assert_eq!(game.piece().y, 2);
+        for _ in 0..4 {
+            assert!(!placed(&game.tap(&[GameInput::Right])));
+        }
+        assert_eq!(game.piece().y, 0);
+
+        // the lowest row is reached the tick after falling off, which gives the whole lock delay
+        // back
+        let mut ticks = 1;
+        while !placed(&game.tick(&[])) {
+            ticks += 1;
+            assert!(ticks <= 20, "the piece never locked");
+        }
+        assert_eq!(ticks, 10);
+    }
+
+    #[test]
+    fn infinite_reset_never_locks_while_moving() {
+        let mut game = on_stack(LockReset::Infinite, [[false; 10]; 40]);
+        assert!(!shuffle(&mut game, 40));
+        let locked = (0..10).any(|_| placed(&game.tick(&[])));
+        assert!(locked);
+    }
+
+    fn falling_t() -> TestGame {
+        let mut game = TestGame::new(GameConfig::fast_config(), [[false; 10]; 40], None, &[Piece::T]);
+        game.tick(&[]);
+        game
+    }
+
+    #[test]
+    fn buttons_released_before_the_next_tick_still_register() {
+        let mut game = falling_t();
+        let x = game.piece().x;
+        game.tap(&[GameInput::Left]);
+        assert_eq!(game.piece().x, x - 1);
+        game.tap(&[GameInput::RotateRight]);
+        assert_eq!(game.piece().kind.1, RotationState::East);
+        assert!(placed(&game.tap(&[GameInput::HardDrop])));
+    }
+
+    #[test]
+    fn press_order_decides_the_result() {
+        // next to the wall, moving left only works once the T is upright
+        let against_wall = || {
+            let mut game = falling_t();
+            while game.piece().x > 1 {
+                game.tap(&[GameInput::Left]);
+            }
+            game
+        };
+
+        let mut game = against_wall();
+        game.tap(&[GameInput::Left, GameInput::RotateRight]);
+        assert_eq!((game.piece().kind.1, game.piece().x), (RotationState::East, 1));
+
+        let mut game = against_wall();
+        game.tap(&[GameInput::RotateRight, GameInput::Left]);
+        assert_eq!((game.piece().kind.1, game.piece().x), (RotationState::East, 0));
+    }
+}
@@ -0,0 +1,90 @@
+use std::path::Path;
+use std::rc::Rc;
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+use serde::{Deserialize, Serialize};
+
+/// When, and how much, garbage is sent to a player practicing on their own.
+///
+/// Units are in ticks.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GarbageSchedule {
+    Fixed {
+        lines: u32,
+        interval: u32,
+    },
+    /// Bursts of between `min` and `max` lines, arriving on average every `interval` ticks.
+    Random {
+        min: u32,
+        max: u32,
+        interval: u32,
+    },
+    /// The attacks of an earlier game, as the tick they arrive on and how many lines were sent,
+    /// in order. Ticks are counted like [`crate::game::Game::ticks`].
+    Timeline(Vec<(u32, u32)>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GarbageScheduler {
+    /// Shared with every snapshot of the scheduler, as timelines can be long.
+    schedule: Rc<GarbageSchedule>,
+    rng: Pcg64Mcg,
+}
+
+impl GarbageScheduler {
+    pub fn new(schedule: GarbageSchedule, seed: [u8; 16]) -> Self {
+        Self {
+            schedule: Rc::new(schedule),
+            rng: Pcg64Mcg::from_seed(seed),
+        }
+    }
+
+    pub fn schedule(&self) -> &GarbageSchedule {
+        &self.schedule
+    }
+
+    /// How many lines of garbage arrive on `tick` of the game, before it's updated. Called once
+    /// for every tick.
+    pub fn update(&mut self, tick: u32) -> u32 {
+        match &*self.schedule {
+            &GarbageSchedule::Fixed { lines, interval } => {
+                if interval > 0 && tick > 0 && tick % interval == 0 {
+                    lines
+                } else {
+                    0
+                }
+            }
+            &GarbageSchedule::Random { min, max, interval } => {
+                if interval > 0 && self.rng.gen_bool(1. / interval as f64) {
+                    self.rng.gen_range(min, max.max(min) + 1)
+                } else {
+                    0
+                }
+            }
+            GarbageSchedule::Timeline(attacks) => {
+                let start = attacks.partition_point(|&(at, _)| at < tick);
+                attacks[start..].iter()
+                    .take_while(|&&(at, _)| at == tick)
+                    .map(|&(_, lines)| lines)
+                    .sum()
+            }
+        }
+    }
+}
+
+pub fn write_timeline(path: &Path, timeline: &[(u32, u32)]) -> Result<(), String> {
+    let src = ron::ser::to_string_pretty(timeline, Default::default()).map_err(|e| e.to_string())?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(path, src).map_err(|e| e.to_string())
+}
+
+pub fn read_timeline(path: &Path) -> Result<Vec<(u32, u32)>, String> {
+    let src = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut timeline: Vec<(u32, u32)> = ron::from_str(&src).map_err(|e| e.to_string())?;
+    // edited by hand, maybe
+    timeline.sort_by_key(|&(tick, _)| tick);
+    Ok(timeline)
+}
@@ -0,0 +1,181 @@
+use std::collections::HashSet;
+
+use rand::{Rng, SeedableRng, thread_rng};
+use rand_pcg::Pcg64Mcg;
+use serde::Serialize;
+
+use crate::analysis::{GameRecord, RecordedPlacement};
+use crate::bot::{BotConfig, BotInput};
+use crate::game::{Event, Game, GameConfig};
+use crate::garbage::{GarbageSchedule, GarbageScheduler};
+use crate::input::InputSource;
+use crate::marathon::{Marathon, MarathonCurve};
+use crate::replay::{Replay, ReplayInput};
+
+/// Bot games that don't top out end after this many ticks: an hour at 60 ticks per second.
+const MAX_TICKS: u32 = 60 * 60 * 60;
+
+/// How a game went, printed as JSON when running headless.
+#[derive(Default, Debug, PartialEq, Serialize)]
+pub struct Stats {
+    pub ticks: u32,
+    pub pieces: u32,
+    pub lines: u32,
+    pub attack: u32,
+    pub max_combo: u32,
+    pub perfect_clears: u32,
+    pub pieces_per_second: f32,
+    pub attack_per_minute: f32,
+    /// Why the game ended, if the player topped out.
+    pub game_over: Option<String>,
+}
+
+impl Stats {
+    fn update(&mut self, events: &[Event]) {
+        for event in events {
+            match event {
+                Event::PiecePlaced { locked, .. } => {
+                    self.pieces += 1;
+                    self.lines += locked.cleared_lines.len() as u32;
+                    self.attack += locked.garbage_sent;
+                    self.max_combo = self.max_combo.max(locked.combo.unwrap_or(0));
+                    if locked.perfect_clear {
+                        self.perfect_clears += 1;
+                    }
+                }
+                Event::GameOver(reason) => self.game_over = Some(reason.to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        let seconds = self.ticks as f32 / 60.;
+        if seconds > 0. {
+            self.pieces_per_second = self.pieces as f32 / seconds;
+            self.attack_per_minute = self.attack as f32 / seconds * 60.;
+        }
+    }
+}
+
+/// The rules and seeds of a game played without a window.
+struct Setup {
+    config: GameConfig,
+    marathon: Option<MarathonCurve>,
+    garbage: Option<GarbageSchedule>,
+    seed: [u8; 16],
+    garbage_seed: [u8; 16],
+}
+
+/// Play `replay` back, the same way it was played in singleplayer.
+pub fn run_replay(replay: Replay) -> Stats {
+    play_replay(replay).0
+}
+
+/// Every placement made in `replay`, to analyse it.
+pub fn record_replay(replay: Replay) -> GameRecord {
+    play_replay(replay).1
+}
+
+fn play_replay(replay: Replay) -> (Stats, GameRecord) {
+    let setup = Setup {
+        config: replay.config,
+        marathon: replay.marathon,
+        garbage: replay.garbage,
+        seed: replay.seed,
+        garbage_seed: replay.garbage_seed,
+    };
+    let mut input = ReplayInput::new(replay.ticks);
+    simulate(setup, &mut input, |input, _| input.is_finished())
+}
+
+/// Let the bot play until it has placed `pieces` pieces or tops out. Given a seed, the game plays
+/// out the same every time.
+pub fn run_bot(
+    config: GameConfig,
+    marathon: Option<MarathonCurve>,
+    bot: BotConfig,
+    seed: Option<[u8; 16]>,
+    pieces: u32,
+) -> Stats {
+    let seed = seed.unwrap_or_else(|| thread_rng().gen());
+    let setup = Setup {
+        config,
+        marathon,
+        garbage: None,
+        seed,
+        garbage_seed: Pcg64Mcg::from_seed(seed).gen(),
+    };
+    let mut input = BotInput::blocking(bot);
+    simulate(setup, &mut input, |_, stats| stats.pieces >= pieces).0
+}
+
+fn simulate<I: InputSource>(
+    setup: Setup,
+    input: &mut I,
+    mut finished: impl FnMut(&I, &Stats) -> bool,
+) -> (Stats, GameRecord) {
+    let mut marathon = setup.marathon.map(Marathon::new);
+    let config = match &marathon {
+        Some(marathon) => marathon.apply(setup.config),
+        None => setup.config,
+    };
+    let mut piece_rng = Pcg64Mcg::from_seed(setup.seed);
+    let mut garbage_rng = Pcg64Mcg::from_seed(setup.garbage_seed);
+    let mut scheduler = setup.garbage
+        .map(|schedule| GarbageScheduler::new(schedule, setup.garbage_seed));
+    let mut game = Game::new(config, &mut piece_rng);
+
+    let keys = HashSet::new();
+    let mut stats = Stats::default();
+    let mut record = GameRecord::default();
+    while !game.is_game_over() && !finished(input, &stats) && stats.ticks < MAX_TICKS {
+        if let Some(scheduler) = &mut scheduler {
+            game.garbage_queue += scheduler.update(game.ticks());
+        }
+
+        // kept to record what the board looked like before a placement
+        let before = game.falling_piece().map(|_| game.board.clone());
+        let controller = input.controller(&keys, None);
+        let presses = input.presses(&[]);
+        let events = game.update_with_presses(controller, &presses, &mut piece_rng, &mut garbage_rng);
+        input.update(&game.board, &events, game.garbage_queue);
+
+        if let Some(marathon) = &mut marathon {
+            let mut level_up = false;
+            for event in &events {
+                level_up |= marathon.update(event);
+            }
+            if level_up {
+                game.set_config(marathon.apply(*game.config()));
+            }
+        }
+
+        let placed = events.iter().find_map(|e| match e {
+            Event::PiecePlaced { piece, .. } => Some(*piece),
+            _ => None,
+        });
+        if let (Some(piece), Some(board)) = (placed, before) {
+            record.placements.push(RecordedPlacement { board, piece });
+        }
+
+        stats.ticks += 1;
+        stats.update(&events);
+    }
+    stats.finish();
+    (stats, record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bot_games_with_the_same_seed_play_out_the_same() {
+        let bot = BotConfig { pps: 3., max_nodes: 500 };
+        let run = || run_bot(GameConfig::fast_config(), None, bot, Some([7; 16]), 30);
+        let first = run();
+        assert_eq!(first.pieces, 30);
+        assert_eq!(first, run());
+    }
+}
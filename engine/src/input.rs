@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use gilrs::{Axis, Button, Gamepad};
+use libtetris::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::Event;
+
+/// The keys of a keyboard, as the window reports them.
+pub use winit::event::VirtualKeyCode as Key;
+
+pub trait InputSource {
+    fn controller(&self, keys: &HashSet<Key>, gamepad: Option<Gamepad<'_>>) -> Controller;
+    fn actions(&self, keys: &HashSet<Key>, gamepad: Option<Gamepad<'_>>) -> GameAction;
+    fn update(
+        &mut self,
+        board: &Board<ColoredRow>,
+        events: &[Event],
+        incoming: u32,
+    );
+    /// Called when a new game starts.
+    fn reset(&mut self) {}
+    /// The controller buttons pressed in `events`, in order.
+    fn presses(&self, _events: &[InputEvent]) -> Vec<GameInput> {
+        vec![]
+    }
+    /// Whether there are no more inputs to come, like at the end of a replay.
+    fn is_finished(&self) -> bool {
+        false
+    }
+}
+
+/// One of the buttons of a [`Controller`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameInput {
+    Left,
+    Right,
+    RotateLeft,
+    RotateRight,
+    Rotate180,
+    HardDrop,
+    SoftDrop,
+    Hold,
+}
+
+impl GameInput {
+    pub fn set(self, controller: &mut Controller, pressed: bool) {
+        let button = match self {
+            GameInput::Left => &mut controller.left,
+            GameInput::Right => &mut controller.right,
+            GameInput::RotateLeft => &mut controller.rotate_left,
+            GameInput::RotateRight => &mut controller.rotate_right,
+            GameInput::Rotate180 => &mut controller.rotate_180,
+            GameInput::HardDrop => &mut controller.hard_drop,
+            GameInput::SoftDrop => &mut controller.soft_drop,
+            GameInput::Hold => &mut controller.hold,
+        };
+        *button = pressed;
+    }
+}
+
+/// A key or gamepad button.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RawInput {
+    Key(Key),
+    Gamepad(Button),
+}
+
+/// A key or gamepad button being pressed or released, at some point between two ticks.
+#[derive(Copy, Clone, Debug)]
+pub struct InputEvent {
+    /// Time since the app started.
+    pub time: Duration,
+    pub input: RawInput,
+    pub pressed: bool,
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct GameAction {
+    pub reset: bool,
+    pub undo: bool,
+    pub redo: bool,
+    pub hint: bool,
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct UserInput {
+    pub keyboard: Config<Key>,
+    pub gamepad: Config<GamepadControl>,
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(bound(deserialize = "T: Deserialize<'de>, Config<T>: Default"))]
+pub struct Config<T> {
+    pub left: T,
+    pub right: T,
+    pub rotate_left: T,
+    pub rotate_right: T,
+    pub rotate_180: T,
+    pub hard_drop: T,
+    pub soft_drop: T,
+    pub hold: T,
+    pub reset: T,
+    // bindings added after settings were first saved, missing from older files
+    #[serde(default = "Config::default_undo")]
+    pub undo: T,
+    #[serde(default = "Config::default_redo")]
+    pub redo: T,
+    #[serde(default = "Config::default_hint")]
+    pub hint: T,
+}
+
+impl Default for Config<Key> {
+    fn default() -> Self {
+        Config {
+            left: Key::Left,
+            right: Key::Right,
+            rotate_left: Key::Z,
+            rotate_right: Key::X,
+            rotate_180: Key::A,
+            hard_drop: Key::Space,
+            soft_drop: Key::Down,
+            hold: Key::C,
+            reset: Key::R,
+            undo: Key::Back,
+            redo: Key::Return,
+            hint: Key::H,
+        }
+    }
+}
+
+impl Default for Config<GamepadControl> {
+    fn default() -> Self {
+        Config {
+            left: GamepadControl::Button(Button::DPadLeft),
+            right: GamepadControl::Button(Button::DPadRight),
+            rotate_left: GamepadControl::Button(Button::South),
+            rotate_right: GamepadControl::Button(Button::East),
+            rotate_180: GamepadControl::Button(Button::North),
+            hard_drop: GamepadControl::Button(Button::DPadUp),
+            soft_drop: GamepadControl::Button(Button::DPadDown),
+            hold: GamepadControl::Button(Button::LeftTrigger),
+            reset: GamepadControl::Button(Button::West),
+            undo: GamepadControl::Button(Button::LeftTrigger2),
+            redo: GamepadControl::Button(Button::RightTrigger2),
+            hint: GamepadControl::Button(Button::Select),
+        }
+    }
+}
+
+impl InputSource for UserInput {
+    fn controller(&self, keys: &HashSet<Key>, gamepad: Option<Gamepad<'_>>) -> Controller {
+        Controller {
+            left: self.read_input(keys, gamepad, self.keyboard.left, self.gamepad.left),
+            right: self.read_input(keys, gamepad, self.keyboard.right, self.gamepad.right),
+            rotate_left: self.read_input(
+                keys,
+                gamepad,
+                self.keyboard.rotate_left,
+                self.gamepad.rotate_left,
+            ),
+            rotate_right: self.read_input(
+                keys,
+                gamepad,
+                self.keyboard.rotate_right,
+                self.gamepad.rotate_right,
+            ),
+            rotate_180: self.read_input(
+                keys,
+                gamepad,
+                self.keyboard.rotate_180,
+                self.gamepad.rotate_180,
+            ),
+            hard_drop: self.read_input(
+                keys,
+                gamepad,
+                self.keyboard.hard_drop,
+                self.gamepad.hard_drop,
+            ),
+            soft_drop: self.read_input(
+                keys,
+                gamepad,
+                self.keyboard.soft_drop,
+                self.gamepad.soft_drop,
+            ),
+            hold: self.read_input(keys, gamepad, self.keyboard.hold, self.gamepad.hold),
+        }
+    }
+
+    fn actions(&self, keys: &HashSet<Key>, gamepad: Option<Gamepad<'_>>) -> GameAction {
+        GameAction {
+            reset: self.read_input(
+                keys,
+                gamepad,
+                self.keyboard.reset,
+                self.gamepad.reset,
+            ),
+            undo: self.read_input(keys, gamepad, self.keyboard.undo, self.gamepad.undo),
+            redo: self.read_input(keys, gamepad, self.keyboard.redo, self.gamepad.redo),
+            hint: self.read_input(keys, gamepad, self.keyboard.hint, self.gamepad.hint),
+        }
+    }
+
+    fn update(&mut self, _: &Board<ColoredRow>, _: &[Event], _: u32) {
+
+    }
+
+    fn presses(&self, events: &[InputEvent]) -> Vec<GameInput> {
+        events.iter()
+            .filter(|event| event.pressed)
+            .filter_map(|event| match event.input {
+                RawInput::Key(key) => self.keyboard.game_input(|&k| k == key),
+                RawInput::Gamepad(button) => self.gamepad.game_input(|&control| {
+                    matches!(control, GamepadControl::Button(b) if b == button)
+                }),
+            })
+            .collect()
+    }
+}
+
+impl<T> Config<T> where Self: Default {
+    fn default_undo() -> T {
+        Self::default().undo
+    }
+
+    fn default_redo() -> T {
+        Self::default().redo
+    }
+
+    fn default_hint() -> T {
+        Self::default().hint
+    }
+}
+
+impl<T> Config<T> {
+    /// The controller button bound to the input matching `bound`, if any.
+    fn game_input(&self, bound: impl Fn(&T) -> bool) -> Option<GameInput> {
+        [
+            (&self.left, GameInput::Left),
+            (&self.right, GameInput::Right),
+            (&self.rotate_left, GameInput::RotateLeft),
+            (&self.rotate_right, GameInput::RotateRight),
+            (&self.rotate_180, GameInput::Rotate180),
+            (&self.hard_drop, GameInput::HardDrop),
+            (&self.soft_drop, GameInput::SoftDrop),
+            (&self.hold, GameInput::Hold),
+        ].into_iter().find(|(input, _)| bound(input)).map(|(_, game_input)| game_input)
+    }
+}
+
+impl UserInput {
+    fn read_input(
+        &self,
+        keys: &HashSet<Key>,
+        controller: Option<Gamepad<'_>>,
+        keyboard: Key,
+        gamepad: GamepadControl,
+    ) -> bool {
+        keys.contains(&keyboard)
+            || controller.map_or(false, |c| match gamepad {
+            GamepadControl::Button(button) => c.is_pressed(button),
+            GamepadControl::PositiveAxis(axis) => c.value(axis) > 0.5,
+            GamepadControl::NegativeAxis(axis) => c.value(axis) < -0.5,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GamepadControl {
+    Button(Button),
+    NegativeAxis(Axis),
+    PositiveAxis(Axis),
+}
@@ -0,0 +1,21 @@
+#![feature(let_else)]
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Everything about playing Practris that doesn't need a window, shared by the window and the
+//! terminal.
+
+pub mod analysis;
+pub mod attack;
+pub mod bot;
+pub mod config;
+pub mod fumen;
+pub mod game;
+pub mod garbage;
+pub mod headless;
+pub mod input;
+pub mod marathon;
+pub mod opener;
+pub mod replay;
+pub mod rotation;
+pub mod singleplayer;
+pub mod sound;
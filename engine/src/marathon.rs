@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Event, GameConfig, TWENTY_G};
+
+const LINES_PER_LEVEL: u32 = 10;
+
+/// Units are in ticks, like [`GameConfig`].
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LevelSpeed {
+    /// Measured in 1/100 of a tick
+    pub gravity: i32,
+    pub lock_delay: u32,
+    pub spawn_delay: u32,
+}
+
+/// The speed of every level of marathon, starting at level 1. Levels past the end of the curve
+/// keep the speed of its last level.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MarathonCurve {
+    pub levels: Vec<LevelSpeed>,
+}
+
+impl Default for MarathonCurve {
+    /// The guideline curve up to level 19, followed by ten levels of 20G where lock delay and
+    /// spawn delay get shorter.
+    fn default() -> Self {
+        let mut levels = vec![];
+        for level in 1..20 {
+            let seconds_per_row = (0.8 - (level - 1) as f32 * 0.007).powi(level - 1);
+            levels.push(LevelSpeed {
+                gravity: ((seconds_per_row * 60. * 100.) as i32).max(TWENTY_G + 1),
+                lock_delay: 30,
+                spawn_delay: 7,
+            });
+        }
+        for level in 0..10 {
+            levels.push(LevelSpeed {
+                gravity: TWENTY_G,
+                lock_delay: 30 - level * 3 / 2,
+                spawn_delay: 7 - level / 3,
+            });
+        }
+        Self { levels }
+    }
+}
+
+/// Tracks the level of a marathon game, which goes up every ten lines.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Marathon {
+    curve: MarathonCurve,
+    lines: u32,
+}
+
+impl Marathon {
+    pub fn new(curve: MarathonCurve) -> Self {
+        Self { curve, lines: 0 }
+    }
+
+    pub fn curve(&self) -> &MarathonCurve {
+        &self.curve
+    }
+
+    pub fn level(&self) -> u32 {
+        1 + self.lines / LINES_PER_LEVEL
+    }
+
+    pub fn lines_to_next_level(&self) -> u32 {
+        LINES_PER_LEVEL - self.lines % LINES_PER_LEVEL
+    }
+
+    /// Count cleared lines, returning whether the level went up.
+    pub fn update(&mut self, event: &Event) -> bool {
+        let Event::PiecePlaced { locked, .. } = event else { return false; };
+        let level = self.level();
+        self.lines += locked.cleared_lines.len() as u32;
+        self.level() != level
+    }
+
+    /// `config`, at the speed of the current level.
+    pub fn apply(&self, config: GameConfig) -> GameConfig {
+        let idx = (self.level() as usize - 1).min(self.curve.levels.len().saturating_sub(1));
+        let Some(speed) = self.curve.levels.get(idx) else { return config; };
+        GameConfig {
+            gravity: speed.gravity,
+            lock_delay: speed.lock_delay,
+            spawn_delay: speed.spawn_delay,
+            ..config
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::path::PathBuf;
+
+use libtetris::{FallingPiece, Piece};
+use serde::{Deserialize, Serialize};
+
+use crate::config::APP_NAME;
+use crate::game::Event;
+
+const BUILTIN: &str = include_str!("openers.ron");
+
+/// An opener, as a set of target placements for the first pieces of the game.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Opener {
+    pub name: String,
+    pub variants: Vec<Variant>,
+}
+
+/// One way of building an opener, which may only work for some orders of the first bag.
+///
+/// The order placements have to be made in follows from their cells: a placement can only be
+/// made once everything below it has been placed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Variant {
+    pub placements: Vec<Target>,
+}
+
+/// A placement of a piece, given as the cells it should occupy on the board.
+///
+/// Because only the cells are compared, all rotation states that result in the same cells are
+/// equally valid.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Target {
+    pub piece: Piece,
+    pub cells: [(i32, i32); 4],
+}
+
+pub fn builtin() -> Vec<Opener> {
+    ron::from_str(BUILTIN).expect("built-in openers are valid")
+}
+
+/// Where openers other than the built-in ones are read from: every `.ron` file in it holds a list
+/// of openers, written like `openers.ron`.
+pub fn openers_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join(APP_NAME).join("openers"))
+}
+
+/// The built-in openers, followed by those in [`openers_dir`].
+pub fn load() -> Vec<Opener> {
+    let mut openers = builtin();
+    let Some(entries) = openers_dir().and_then(|dir| std::fs::read_dir(dir).ok()) else { return openers; };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "ron"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let read = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|src| ron::from_str::<Vec<Opener>>(&src).map_err(|e| e.to_string()));
+        match read {
+            Ok(read) => openers.extend(read),
+            Err(e) => log::error!("Failed to read openers from {path:?}: {e}"),
+        }
+    }
+    openers
+}
+
+impl Target {
+    pub fn matches(&self, piece: &FallingPiece) -> bool {
+        let mut cells = piece.cells();
+        let mut target = self.cells;
+        cells.sort_unstable();
+        target.sort_unstable();
+        piece.kind.0 == self.piece && cells == target
+    }
+}
+
+impl Variant {
+    /// Whether `other` has to be placed before `target`, because it lies below it.
+    fn depends_on(&self, target: usize, other: usize) -> bool {
+        let cells = &self.placements[target].cells;
+        target != other && self.placements[other].cells.iter()
+            .any(|&(ox, oy)| cells.iter().any(|&(x, y)| x == ox && oy < y))
+    }
+
+    fn placeable(&self, target: usize, placed: &[bool]) -> bool {
+        (0..self.placements.len()).all(|other| placed[other] || !self.depends_on(target, other))
+    }
+
+    /// The first placement for `piece` that can be made right now, if any.
+    fn next_for(&self, piece: Piece, placed: &[bool]) -> Option<usize> {
+        (0..self.placements.len())
+            .find(|&idx| !placed[idx] && self.placements[idx].piece == piece && self.placeable(idx, placed))
+    }
+
+    /// Whether this variant can be built when pieces come in the order they were `seen`,
+    /// using hold to set aside a piece that can't be placed yet.
+    fn allows(&self, seen: &[Piece]) -> bool {
+        let mut placed = vec![false; self.placements.len()];
+        let mut hold = None;
+        for &piece in seen {
+            if let Some(idx) = self.next_for(piece, &placed) {
+                placed[idx] = true;
+                if let Some(idx) = hold.and_then(|held| self.next_for(held, &placed)) {
+                    placed[idx] = true;
+                    hold = None;
+                }
+            } else if hold.is_none() {
+                hold = Some(piece);
+            } else {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum OpenerStatus {
+    Building,
+    Completed,
+    /// A piece was placed somewhere that isn't part of any variant of the opener.
+    Deviated(FallingPiece),
+}
+
+/// Tracks the player's progress through an opener as pieces are placed.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OpenerTrainer {
+    opener: Opener,
+    /// Every variant that is still consistent with the placements so far, along with which of
+    /// its placements have been made.
+    candidates: Vec<(usize, Vec<bool>)>,
+    /// The pieces of the first bag, in the order they appeared.
+    seen: Vec<Piece>,
+    status: OpenerStatus,
+}
+
+impl OpenerTrainer {
+    pub fn new(opener: Opener, queue: impl IntoIterator<Item=Piece>) -> Self {
+        let candidates = opener.variants.iter()
+            .enumerate()
+            .map(|(idx, variant)| (idx, vec![false; variant.placements.len()]))
+            .collect();
+        let mut trainer = Self {
+            opener,
+            candidates,
+            seen: vec![],
+            status: OpenerStatus::Building,
+        };
+        for piece in queue {
+            trainer.see(piece);
+        }
+        trainer
+    }
+
+    pub fn opener(&self) -> &Opener {
+        &self.opener
+    }
+
+    pub fn status(&self) -> OpenerStatus {
+        self.status
+    }
+
+    fn see(&mut self, piece: Piece) {
+        if self.seen.len() < 7 {
+            self.seen.push(piece);
+        }
+    }
+
+    pub fn update(&mut self, event: &Event) {
+        if !matches!(self.status, OpenerStatus::Building) {
+            return;
+        }
+
+        match event {
+            Event::PieceSpawned { new_in_queue } => self.see(*new_in_queue),
+            Event::PiecePlaced { piece, .. } => {
+                let variants = &self.opener.variants;
+                let seen = &self.seen;
+                self.candidates = std::mem::take(&mut self.candidates).into_iter()
+                    .filter_map(|(idx, mut placed)| {
+                        let variant = &variants[idx];
+                        if !variant.allows(seen) {
+                            return None;
+                        }
+                        let target = (0..variant.placements.len())
+                            .find(|&idx| !placed[idx] && variant.placements[idx].matches(piece))?;
+                        placed[target] = true;
+                        Some((idx, placed))
+                    })
+                    .collect();
+
+                if self.candidates.is_empty() {
+                    self.status = OpenerStatus::Deviated(*piece);
+                } else if self.candidates.iter().any(|(_, placed)| placed.iter().all(|&p| p)) {
+                    self.status = OpenerStatus::Completed;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The target the player should be going for next, given the piece they currently control and
+    /// the one they have in hold. If the current piece can't be placed yet, the hold piece's
+    /// target is given instead; if neither can, there is no target and the player should hold.
+    pub fn next_target(&self, current: Option<Piece>, hold: Option<Piece>) -> Option<Target> {
+        let (idx, placed) = self.candidates.iter()
+            .find(|(idx, _)| self.opener.variants[*idx].allows(&self.seen))?;
+        let variant = &self.opener.variants[*idx];
+
+        [current, hold].into_iter()
+            .flatten()
+            .find_map(|piece| variant.next_for(piece, placed))
+            .map(|target| variant.placements[target])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libtetris::{Board, ColoredRow, PieceState, RotationState, TspinStatus};
+
+    use super::*;
+
+    fn falling(piece: Piece, x: i32) -> FallingPiece {
+        FallingPiece { kind: PieceState(piece, RotationState::North), x, y: 0, tspin: TspinStatus::None }
+    }
+
+    fn placed(piece: FallingPiece) -> Event {
+        Event::PiecePlaced {
+            piece,
+            locked: Board::<ColoredRow>::new().lock_piece(piece),
+            hard_drop_distance: None,
+        }
+    }
+
+    /// An O on the left and an I on the right, which can be placed in any order.
+    fn trainer() -> (OpenerTrainer, FallingPiece, FallingPiece) {
+        let o = falling(Piece::O, 0);
+        let i = falling(Piece::I, 5);
+        let opener = Opener {
+            name: "Test".to_string(),
+            variants: vec![Variant {
+                placements: vec![
+                    Target { piece: Piece::O, cells: o.cells() },
+                    Target { piece: Piece::I, cells: i.cells() },
+                ],
+            }],
+        };
+        (OpenerTrainer::new(opener, [Piece::O, Piece::I]), o, i)
+    }
+
+    #[test]
+    fn placing_every_target_completes_the_opener() {
+        let (mut trainer, o, i) = trainer();
+        assert_eq!(trainer.next_target(Some(Piece::O), None).unwrap().cells, o.cells());
+
+        trainer.update(&placed(o));
+        assert!(matches!(trainer.status(), OpenerStatus::Building));
+        assert_eq!(trainer.next_target(Some(Piece::I), None).unwrap().cells, i.cells());
+
+        trainer.update(&placed(i));
+        assert!(matches!(trainer.status(), OpenerStatus::Completed));
+    }
+
+    #[test]
+    fn a_misplaced_piece_is_a_deviation() {
+        let (mut trainer, _, _) = trainer();
+        let wrong = falling(Piece::O, 3);
+        trainer.update(&placed(wrong));
+        assert!(matches!(trainer.status(), OpenerStatus::Deviated(piece) if piece.x == wrong.x));
+    }
+
+    #[test]
+    fn variants_that_need_two_pieces_held_are_not_offered() {
+        let (trainer, _, _) = trainer();
+        let variant = &trainer.opener().variants[0];
+        assert!(variant.allows(&[Piece::T, Piece::O, Piece::I]));
+        assert!(!variant.allows(&[Piece::T, Piece::S, Piece::O]));
+    }
+
+    #[test]
+    fn builtin_openers_are_valid() {
+        for opener in builtin() {
+            for variant in &opener.variants {
+                assert_eq!(variant.placements.len(), 7, "{}", opener.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use gilrs::Gamepad;
+use libtetris::{Board, ColoredRow, Controller};
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Event, GameConfig};
+use crate::garbage::GarbageSchedule;
+use crate::input::{GameAction, GameInput, InputEvent, InputSource, Key};
+use crate::marathon::MarathonCurve;
+
+/// Everything needed to play a singleplayer game back exactly: the rules and seeds it started
+/// with, and the input of every tick.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub config: GameConfig,
+    pub marathon: Option<MarathonCurve>,
+    pub garbage: Option<GarbageSchedule>,
+    pub seed: [u8; 16],
+    /// Seeds both the garbage holes and the garbage schedule.
+    pub garbage_seed: [u8; 16],
+    pub ticks: Vec<ReplayTick>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReplayTick {
+    pub controller: Controller,
+    /// Buttons pressed during the tick, see [`crate::game::Game::update_with_presses`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub presses: Vec<GameInput>,
+}
+
+/// Plays back the inputs of a replay, one tick at a time.
+pub struct ReplayInput {
+    ticks: Vec<ReplayTick>,
+    tick: usize,
+}
+
+impl ReplayInput {
+    pub fn new(ticks: Vec<ReplayTick>) -> Self {
+        Self { ticks, tick: 0 }
+    }
+}
+
+impl InputSource for ReplayInput {
+    fn controller(&self, _: &HashSet<Key>, _: Option<Gamepad<'_>>) -> Controller {
+        self.ticks.get(self.tick).map_or_else(Controller::default, |tick| tick.controller)
+    }
+
+    fn actions(&self, _: &HashSet<Key>, _: Option<Gamepad<'_>>) -> GameAction {
+        GameAction::default()
+    }
+
+    fn update(&mut self, _: &Board<ColoredRow>, _: &[Event], _: u32) {
+        self.tick += 1;
+    }
+
+    fn reset(&mut self) {
+        self.tick = 0;
+    }
+
+    fn presses(&self, _: &[InputEvent]) -> Vec<GameInput> {
+        self.ticks.get(self.tick).map_or_else(Vec::new, |tick| tick.presses.clone())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.tick >= self.ticks.len()
+    }
+}
+
+pub fn write_replay(path: &Path, replay: &Replay) -> Result<(), String> {
+    let src = ron::ser::to_string_pretty(replay, Default::default()).map_err(|e| e.to_string())?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(path, src).map_err(|e| e.to_string())
+}
+
+pub fn read_replay(path: &Path) -> Result<Replay, String> {
+    let src = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    ron::from_str(&src).map_err(|e| e.to_string())
+}
@@ -0,0 +1,370 @@
+use libtetris::{Board, ColoredRow, FallingPiece, Piece, RotationState, TspinStatus};
+use serde::{Deserialize, Serialize};
+
+/// How pieces rotate, and where they are moved to when the rotated piece doesn't fit.
+///
+/// Kick tables are given the way they are usually written down, for pieces rotating around the
+/// centre of their bounding box, with y pointing up.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum RotationSystem {
+    /// The guideline Super Rotation System. SRS has no 180 rotations, so those are done the way
+    /// libtetris does them.
+    Srs,
+    /// SRS as played in TETR.IO, with symmetric I kicks and 180 kicks.
+    SrsPlus,
+    /// SRS with the large 180 kick tables of SRS-X.
+    SrsX,
+    /// Rotation without any kicks.
+    Classic,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    Cw,
+    Ccw,
+    Half,
+}
+
+/// A successful rotation, and which of the kicks it took.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Kick {
+    pub piece: Piece,
+    pub from: RotationState,
+    pub to: RotationState,
+    /// Which test of the kick table succeeded, 0 being the unkicked rotation.
+    pub index: usize,
+    pub offset: (i32, i32),
+}
+
+type Table = &'static [(i32, i32)];
+
+const NO_KICKS: Table = &[(0, 0)];
+
+// JLSTZ kicks, for 0->R, R->0, R->2, 2->R, 2->L, L->2, L->0 and 0->L
+const JLSTZ: [Table; 8] = [
+    &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    &[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+    &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+    &[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+];
+
+const SRS_I: [Table; 8] = [
+    &[(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+    &[(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    &[(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    &[(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+    &[(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    &[(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+    &[(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+    &[(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+];
+
+const SRS_PLUS_I: [Table; 8] = [
+    &[(0, 0), (1, 0), (-2, 0), (-2, -1), (1, 2)],
+    &[(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)],
+    &[(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    &[(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)],
+    &[(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    &[(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+    &[(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)],
+    &[(0, 0), (-1, 0), (2, 0), (2, -1), (-1, 2)],
+];
+
+// 180 kicks, for 0->2, 2->0, R->L and L->R
+const SRS_PLUS_180: [Table; 4] = [
+    &[(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)],
+    &[(0, 0), (0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)],
+    &[(0, 0), (1, 0), (1, 2), (1, 1), (0, 2), (0, 1)],
+    &[(0, 0), (-1, 0), (-1, 2), (-1, 1), (0, 2), (0, 1)],
+];
+
+const SRS_X_180: [Table; 4] = [
+    &[
+        (0, 0), (1, 0), (2, 0), (1, 1), (2, 1), (-1, 0),
+        (-2, 0), (-1, 1), (-2, 1), (0, -1), (3, 0), (-3, 0),
+    ],
+    &[
+        (0, 0), (-1, 0), (-2, 0), (-1, -1), (-2, -1), (1, 0),
+        (2, 0), (1, -1), (2, -1), (0, 1), (-3, 0), (3, 0),
+    ],
+    &[
+        (0, 0), (0, 1), (0, 2), (-1, 1), (-1, 2), (0, -1),
+        (0, -2), (-1, -1), (-1, -2), (1, 0), (0, 3), (0, -3),
+    ],
+    &[
+        (0, 0), (0, 1), (0, 2), (1, 1), (1, 2), (0, -1),
+        (0, -2), (1, -1), (1, -2), (-1, 0), (0, 3), (0, -3),
+    ],
+];
+
+/// libtetris rotates every piece around one of its cells. These are the offsets that move the
+/// rotated piece to where it would be when rotating around the centre of its bounding box, which
+/// is what kick tables assume.
+fn true_rotation_offset(piece: Piece, state: RotationState) -> (i32, i32) {
+    match (piece, state) {
+        (Piece::I, RotationState::North) => (0, 0),
+        (Piece::I, RotationState::East) => (-1, 0),
+        (Piece::I, RotationState::South) => (-1, 1),
+        (Piece::I, RotationState::West) => (0, 1),
+        (Piece::O, RotationState::North) => (0, 0),
+        (Piece::O, RotationState::East) => (0, -1),
+        (Piece::O, RotationState::South) => (-1, -1),
+        (Piece::O, RotationState::West) => (-1, 0),
+        _ => (0, 0),
+    }
+}
+
+fn rotated(state: RotationState, rotation: Rotation) -> RotationState {
+    use RotationState::*;
+    match (state, rotation) {
+        (North, Rotation::Cw) | (South, Rotation::Ccw) | (West, Rotation::Half) => East,
+        (East, Rotation::Cw) | (West, Rotation::Ccw) | (North, Rotation::Half) => South,
+        (South, Rotation::Cw) | (North, Rotation::Ccw) | (East, Rotation::Half) => West,
+        (West, Rotation::Cw) | (East, Rotation::Ccw) | (South, Rotation::Half) => North,
+    }
+}
+
+/// Index into a table of 90 degree kicks.
+fn quarter_index(from: RotationState, to: RotationState) -> usize {
+    use RotationState::*;
+    match (from, to) {
+        (North, East) => 0,
+        (East, North) => 1,
+        (East, South) => 2,
+        (South, East) => 3,
+        (South, West) => 4,
+        (West, South) => 5,
+        (West, North) => 6,
+        _ => 7,
+    }
+}
+
+/// Index into a table of 180 degree kicks.
+fn half_index(from: RotationState) -> usize {
+    match from {
+        RotationState::North => 0,
+        RotationState::South => 1,
+        RotationState::East => 2,
+        RotationState::West => 3,
+    }
+}
+
+impl RotationSystem {
+    fn kicks(
+        &self,
+        piece: Piece,
+        from: RotationState,
+        to: RotationState,
+        rotation: Rotation,
+    ) -> Table {
+        if piece == Piece::O {
+            return NO_KICKS;
+        }
+        match (self, rotation) {
+            (RotationSystem::Classic, _) => NO_KICKS,
+            // done by libtetris instead, see `rotate`
+            (RotationSystem::Srs, Rotation::Half) => NO_KICKS,
+            (RotationSystem::SrsPlus, Rotation::Half) => SRS_PLUS_180[half_index(from)],
+            (RotationSystem::SrsX, Rotation::Half) => SRS_X_180[half_index(from)],
+            (RotationSystem::SrsPlus, _) if piece == Piece::I => {
+                SRS_PLUS_I[quarter_index(from, to)]
+            }
+            (_, _) if piece == Piece::I => SRS_I[quarter_index(from, to)],
+            (_, _) => JLSTZ[quarter_index(from, to)],
+        }
+    }
+
+    /// Rotate `piece`, trying every kick until one fits. Returns which kick was taken, or `None`
+    /// if the piece couldn't be rotated.
+    pub fn rotate(
+        &self,
+        board: &Board<ColoredRow>,
+        piece: &mut FallingPiece,
+        rotation: Rotation,
+    ) -> Option<Kick> {
+        let kind = piece.kind.0;
+        let from = piece.kind.1;
+        let to = rotated(from, rotation);
+        let (from_x, from_y) = true_rotation_offset(kind, from);
+        let (to_x, to_y) = true_rotation_offset(kind, to);
+
+        if *self == RotationSystem::Srs && rotation == Rotation::Half {
+            let before = *piece;
+            if !piece.flip(board) {
+                return None;
+            }
+            // libtetris doesn't say which of its kicks it took, only whether the piece moved
+            let offset = (piece.x - before.x - from_x + to_x, piece.y - before.y - from_y + to_y);
+            let index = if offset == (0, 0) { 0 } else { 1 };
+            return Some(Kick { piece: kind, from, to, index, offset });
+        }
+
+        for (index, &(dx, dy)) in self.kicks(kind, from, to, rotation).iter().enumerate() {
+            let mut candidate = *piece;
+            candidate.kind.1 = to;
+            candidate.x += dx + from_x - to_x;
+            candidate.y += dy + from_y - to_y;
+            if board.obstructed(&candidate) {
+                continue;
+            }
+
+            // the last 90 degree SRS kick is the one that makes T-spin triples possible, and
+            // always counts as a full T-spin
+            let last_kick = rotation != Rotation::Half && index == 4;
+            candidate.tspin = tspin_status(board, &candidate, last_kick);
+            *piece = candidate;
+            return Some(Kick { piece: kind, from, to, index, offset: (dx, dy) });
+        }
+        None
+    }
+}
+
+/// The 3-corner T-spin rule: a T piece with three of the four cells diagonal to its centre
+/// occupied is a T-spin, and a mini T-spin unless both cells it points towards are occupied.
+fn tspin_status(board: &Board<ColoredRow>, piece: &FallingPiece, last_kick: bool) -> TspinStatus {
+    if piece.kind.0 != Piece::T {
+        return TspinStatus::None;
+    }
+
+    let occupied = |dx: i32, dy: i32| board.occupied(piece.x + dx, piece.y + dy);
+    let (front, back) = match piece.kind.1 {
+        RotationState::North => ([(-1, 1), (1, 1)], [(-1, -1), (1, -1)]),
+        RotationState::East => ([(1, 1), (1, -1)], [(-1, 1), (-1, -1)]),
+        RotationState::South => ([(-1, -1), (1, -1)], [(-1, 1), (1, 1)]),
+        RotationState::West => ([(-1, 1), (-1, -1)], [(1, 1), (1, -1)]),
+    };
+    let front = front.iter().filter(|&&(dx, dy)| occupied(dx, dy)).count();
+    let back = back.iter().filter(|&&(dx, dy)| occupied(dx, dy)).count();
+
+    if front + back < 3 {
+        TspinStatus::None
+    } else if front == 2 || last_kick {
+        TspinStatus::Full
+    } else {
+        TspinStatus::Mini
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libtetris::PieceState;
+
+    use super::*;
+
+    /// A board from rows drawn top to bottom, ending at the bottom row, `#` being a filled cell.
+    fn board(rows: &[&str]) -> Board<ColoredRow> {
+        let mut field = [[false; 10]; 40];
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, cell) in row.chars().enumerate() {
+                field[y][x] = cell == '#';
+            }
+        }
+        let bag = Board::<ColoredRow>::new().bag;
+        Board::new_with_state(field, bag, None, false, 0)
+    }
+
+    fn piece(kind: Piece, state: RotationState, x: i32, y: i32) -> FallingPiece {
+        FallingPiece { kind: PieceState(kind, state), x, y, tspin: TspinStatus::None }
+    }
+
+    fn sorted_cells(piece: &FallingPiece) -> [(i32, i32); 4] {
+        let mut cells = piece.cells();
+        cells.sort_unstable();
+        cells
+    }
+
+    #[test]
+    fn i_kicks_follow_each_system() {
+        // a single block in the way of rotating a flat I upright
+        let mut rows = vec![".....#...."];
+        rows.extend([".........."; 9]);
+        let field = board(&rows);
+
+        let rotate = |system: RotationSystem| {
+            let mut i = piece(Piece::I, RotationState::North, 4, 10);
+            system.rotate(&field, &mut i, Rotation::Cw).map(|kick| (kick.index, kick.offset, sorted_cells(&i)))
+        };
+        let column = |x| [(x, 8), (x, 9), (x, 10), (x, 11)];
+        assert_eq!(rotate(RotationSystem::Srs), Some((1, (-2, 0), column(3))));
+        assert_eq!(rotate(RotationSystem::SrsX), Some((1, (-2, 0), column(3))));
+        assert_eq!(rotate(RotationSystem::SrsPlus), Some((1, (1, 0), column(6))));
+        assert_eq!(rotate(RotationSystem::Classic), None);
+    }
+
+    #[test]
+    fn half_rotations_kick_in_srs_plus_and_srs_x() {
+        let field = board(&["....#....."]);
+        let rotate = |system: RotationSystem| {
+            let mut t = piece(Piece::T, RotationState::North, 4, 1);
+            system.rotate(&field, &mut t, Rotation::Half).map(|kick| (kick.index, kick.offset, t.x, t.y))
+        };
+        assert_eq!(rotate(RotationSystem::SrsPlus), Some((1, (0, 1), 4, 2)));
+        assert_eq!(rotate(RotationSystem::SrsX), Some((1, (1, 0), 5, 1)));
+        assert_eq!(rotate(RotationSystem::Classic), None);
+    }
+
+    #[test]
+    fn srs_half_rotations_are_libtetris_flips() {
+        let field = board(&["....#....."]);
+        for (x, y) in [(4, 1), (4, 5)] {
+            let mut expected = piece(Piece::T, RotationState::North, x, y);
+            let flipped = expected.flip(&field);
+            let mut t = piece(Piece::T, RotationState::North, x, y);
+            let kick = RotationSystem::Srs.rotate(&field, &mut t, Rotation::Half);
+            assert_eq!(kick.is_some(), flipped);
+            assert_eq!(sorted_cells(&t), sorted_cells(&expected));
+        }
+    }
+
+    #[test]
+    fn t_spin_double_is_a_full_t_spin() {
+        let field = board(&[
+            "#.........",
+            "...#######",
+            "#.########",
+        ]);
+        let mut t = piece(Piece::T, RotationState::East, 1, 1);
+        let kick = RotationSystem::Srs.rotate(&field, &mut t, Rotation::Cw).unwrap();
+        assert_eq!(kick.index, 0);
+        assert_eq!((t.kind.1, t.x, t.y), (RotationState::South, 1, 1));
+        assert_eq!(t.tspin, TspinStatus::Full);
+    }
+
+    #[test]
+    fn three_corners_with_one_in_front_is_a_mini() {
+        let field = board(&[".#########"]);
+        let mut t = piece(Piece::T, RotationState::North, 1, 1);
+        let kick = RotationSystem::Srs.rotate(&field, &mut t, Rotation::Cw).unwrap();
+        assert_eq!((kick.index, kick.offset), (1, (-1, 0)));
+        assert_eq!((t.kind.1, t.x, t.y), (RotationState::East, 0, 1));
+        assert_eq!(t.tspin, TspinStatus::Mini);
+    }
+
+    #[test]
+    fn the_last_kick_makes_a_full_t_spin() {
+        // the T only fits two rows down, where just one of the corners in front of it is filled
+        let field = board(&[
+            ".#........",
+            "..........",
+            "#.#.......",
+            "#..#######",
+            "#..#######",
+        ]);
+        let mut t = piece(Piece::T, RotationState::North, 2, 3);
+        let kick = RotationSystem::Srs.rotate(&field, &mut t, Rotation::Cw).unwrap();
+        assert_eq!((kick.index, kick.offset), (4, (-1, -2)));
+        assert_eq!((t.kind.1, t.x, t.y), (RotationState::East, 1, 1));
+        assert_eq!(t.tspin, TspinStatus::Full);
+    }
+
+    #[test]
+    fn rotating_without_corners_is_no_t_spin() {
+        let mut t = piece(Piece::T, RotationState::North, 4, 5);
+        RotationSystem::Srs.rotate(&board(&[]), &mut t, Rotation::Cw).unwrap();
+        assert_eq!(t.tspin, TspinStatus::None);
+    }
+}
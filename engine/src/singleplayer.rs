@@ -0,0 +1,728 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use gilrs::Gamepad;
+use rand::{Rng, SeedableRng, thread_rng};
+use rand_pcg::Pcg64Mcg;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{GameRecord, RecordedPlacement};
+use crate::bot::{HintSearch, Suggestion};
+use crate::fumen::{self, Position};
+use crate::game::{Event, Game, GameConfig, PlayerUpdate};
+use crate::garbage::{GarbageSchedule, GarbageScheduler};
+use crate::input::{GameAction, InputEvent, InputSource, Key};
+use crate::marathon::{Marathon, MarathonCurve};
+use crate::opener::{Opener, OpenerStatus, OpenerTrainer};
+use crate::replay::{Replay, ReplayInput, ReplayTick};
+use crate::rotation::Kick;
+
+const RESET_TIME: usize = 40;
+/// How long the result of an opener attempt is shown before the next attempt starts.
+const OPENER_RESTART_TIME: u32 = 60;
+/// How many of the bot's suggestions are shown at once.
+const HINT_COUNT: usize = 3;
+
+/// A singleplayer game and everything around it except drawing: restarting, undo and redo,
+/// openers, hints, garbage, marathon and replays.
+pub struct Singleplayer {
+    game: Game,
+    /// The rules new games are started with, before marathon changes their speed.
+    config: GameConfig,
+    input: Box<dyn InputSource>,
+    /// The player's own input while a replay is played back in its place.
+    user_input: Option<Box<dyn InputSource>>,
+    state: State,
+    piece_rng: Pcg64Mcg,
+    garbage_rng: Pcg64Mcg,
+    reset_countdown: f32,
+    prev_actions: GameAction,
+    /// Snapshots taken after every placement, the last being the most recent one.
+    history: Vec<Snapshot>,
+    /// Snapshots that were undone, the last being the first to be redone.
+    future: Vec<Snapshot>,
+    /// Placements made before the state the game was loaded from, which can't be undone.
+    loaded_placements: Vec<RecordedPlacement>,
+    opener: Option<OpenerTrainer>,
+    opener_restart: Option<u32>,
+    /// Show hints for every new piece, rather than only when asked for.
+    coaching: bool,
+    hints: Vec<Suggestion>,
+    /// The search for hints for the current piece, while the bot is still thinking.
+    hint_search: Option<HintSearch>,
+    garbage: Option<GarbageScheduler>,
+    marathon: Option<Marathon>,
+    /// Every attack sent this game, as the tick it would arrive on and how many lines were sent,
+    /// including attacks that were undone.
+    attacks: Vec<(u32, u32)>,
+    /// How many of `attacks` were sent to get to the current point in the game.
+    attack_count: usize,
+    /// The seed every game starts with, instead of a random one.
+    seed: Option<[u8; 16]>,
+    /// The game being recorded since it started, until undo or loading a state makes it impossible
+    /// to play back.
+    replay: Option<Replay>,
+    /// The position every game starts from, instead of an empty board.
+    start_position: Option<Position>,
+    /// Ticks the game has been played for, not counting the countdown before it.
+    time: u32,
+    /// Events since they were last taken.
+    events: Vec<Event>,
+    /// How the game jumped since this was last taken.
+    jump: Option<Jump>,
+}
+
+/// A change to the game that isn't described by its events, after which it has to be drawn anew.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Jump {
+    /// A new game was started.
+    Restarted,
+    /// The game went back or forward to another point, through undo, redo or loading a state.
+    Restored,
+}
+
+/// A point in the game that can be returned to through undo and redo.
+#[derive(Clone, Serialize, Deserialize)]
+struct Snapshot {
+    game: Game,
+    piece_rng: Pcg64Mcg,
+    garbage_rng: Pcg64Mcg,
+    opener: Option<OpenerTrainer>,
+    garbage: Option<GarbageScheduler>,
+    marathon: Option<Marathon>,
+    /// How many attacks were sent to get here.
+    #[serde(default)]
+    attack_count: usize,
+    /// The placement that led to this snapshot, if it was taken after one.
+    placement: Option<RecordedPlacement>,
+}
+
+/// Everything needed to resume a game exactly where it was left off, down to the falling piece
+/// and its lock delay.
+#[derive(Serialize, Deserialize)]
+pub struct SaveState {
+    snapshot: Snapshot,
+    state: State,
+    time: u32,
+    /// Every attack sent to get to the snapshot.
+    #[serde(default)]
+    attacks: Vec<(u32, u32)>,
+    /// Every placement made to get to the snapshot, so the game can still be analysed.
+    #[serde(default)]
+    record: GameRecord,
+}
+
+impl SaveState {
+    pub fn record(&self) -> &GameRecord {
+        &self.record
+    }
+}
+
+pub fn write_state(path: &Path, state: &SaveState) -> Result<(), String> {
+    let src = ron::ser::to_string_pretty(state, Default::default()).map_err(|e| e.to_string())?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(path, src).map_err(|e| e.to_string())
+}
+
+pub fn read_state(path: &Path) -> Result<SaveState, String> {
+    let src = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    ron::from_str(&src).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum State {
+    Playing,
+    GameOver(u32),
+    Starting(u32),
+}
+
+impl Singleplayer {
+    pub fn new(input: Box<dyn InputSource>) -> Self {
+        let mut thread_rng = thread_rng();
+        let mut rng = Pcg64Mcg::from_seed(thread_rng.gen());
+        let mut garbage_rng = Pcg64Mcg::from_seed(thread_rng.gen());
+        let game = Game::new(GameConfig::fast_config(), &mut rng);
+
+        let mut this = Self {
+            game,
+            config: GameConfig::fast_config(),
+            input,
+            user_input: None,
+            state: State::Starting(300),
+            piece_rng: rng,
+            garbage_rng,
+            reset_countdown: 1.,
+            prev_actions: GameAction::default(),
+            history: vec![],
+            future: vec![],
+            loaded_placements: vec![],
+            opener: None,
+            opener_restart: None,
+            coaching: false,
+            hints: vec![],
+            hint_search: None,
+            garbage: None,
+            marathon: None,
+            attacks: vec![],
+            attack_count: 0,
+            seed: None,
+            replay: None,
+            start_position: None,
+            time: 0,
+            events: vec![],
+            jump: None,
+        };
+        this.history.push(this.snapshot());
+        this
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn time(&self) -> u32 {
+        self.time
+    }
+
+    /// How far the reset button is from restarting the game, from 1 when it isn't held down to 0.
+    pub fn reset_countdown(&self) -> f32 {
+        self.reset_countdown
+    }
+
+    pub fn hints(&self) -> &[Suggestion] {
+        &self.hints
+    }
+
+    pub fn opener(&self) -> Option<&OpenerTrainer> {
+        self.opener.as_ref()
+    }
+
+    pub fn marathon(&self) -> Option<&Marathon> {
+        self.marathon.as_ref()
+    }
+
+    /// Events since they were last taken.
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Whether the game jumped since this was last called, a restart taking precedence.
+    pub fn take_jump(&mut self) -> Option<Jump> {
+        self.jump.take()
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            game: self.game.clone(),
+            piece_rng: self.piece_rng.clone(),
+            garbage_rng: self.garbage_rng.clone(),
+            opener: self.opener.clone(),
+            garbage: self.garbage.clone(),
+            marathon: self.marathon.clone(),
+            attack_count: self.attack_count,
+            placement: None,
+        }
+    }
+
+    /// Every placement made to get to the current point in the game, for analysis.
+    pub fn record(&self) -> GameRecord {
+        GameRecord {
+            placements: self.loaded_placements.iter()
+                .cloned()
+                .chain(self.history.iter().filter_map(|snapshot| snapshot.placement.clone()))
+                .collect(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.game = snapshot.game;
+        self.piece_rng = snapshot.piece_rng;
+        self.garbage_rng = snapshot.garbage_rng;
+        self.opener = snapshot.opener;
+        self.garbage = snapshot.garbage;
+        self.marathon = snapshot.marathon;
+        self.attack_count = snapshot.attack_count.min(self.attacks.len());
+        self.opener_restart = None;
+        self.state = State::Playing;
+        self.replay = None;
+        self.stop_replay();
+        self.clear_hints();
+        self.jump.get_or_insert(Jump::Restored);
+    }
+
+    /// Start over with a fresh game.
+    pub fn restart(&mut self) {
+        self.stop_replay();
+        let seed = self.seed.unwrap_or_else(|| thread_rng().gen());
+        self.start(seed, thread_rng().gen());
+    }
+
+    /// Start over with a fresh game from the given seeds.
+    fn start(&mut self, seed: [u8; 16], garbage_seed: [u8; 16]) {
+        let mut rng = Pcg64Mcg::from_seed(seed);
+        if let Some(marathon) = &mut self.marathon {
+            *marathon = Marathon::new(marathon.curve().clone());
+        }
+        let config = match &self.marathon {
+            Some(marathon) => marathon.apply(self.config),
+            None => self.config,
+        };
+        let game = match &self.start_position {
+            Some(position) => Game::from_position(config, position.field, position.hold, &position.queue, &mut rng),
+            None => Game::new(config, &mut rng),
+        };
+        if let Some(trainer) = &mut self.opener {
+            *trainer = OpenerTrainer::new(trainer.opener().clone(), game.board.next_queue());
+        }
+        self.opener_restart = None;
+        self.clear_hints();
+        if let Some(scheduler) = &mut self.garbage {
+            *scheduler = GarbageScheduler::new(scheduler.schedule().clone(), garbage_seed);
+        }
+        self.attacks.clear();
+        self.attack_count = 0;
+        // replays always start from an empty board
+        self.replay = self.start_position.is_none().then(|| Replay {
+            config: self.config,
+            marathon: self.marathon.as_ref().map(|marathon| marathon.curve().clone()),
+            garbage: self.garbage.as_ref().map(|scheduler| scheduler.schedule().clone()),
+            seed,
+            garbage_seed,
+            ticks: vec![],
+        });
+        self.input.reset();
+        self.game = game;
+        self.state = State::Playing;
+        self.piece_rng = rng;
+        self.garbage_rng = Pcg64Mcg::from_seed(garbage_seed);
+        self.time = 0;
+        self.future.clear();
+        self.history.clear();
+        self.loaded_placements.clear();
+        self.history.push(self.snapshot());
+        self.jump = Some(Jump::Restarted);
+    }
+
+    /// Change the rules of the game, taking effect immediately.
+    pub fn set_config(&mut self, config: GameConfig) {
+        if config != self.config {
+            // a replay is played back with the rules it started with, so the game can only be
+            // played back if it didn't start yet
+            match &mut self.replay {
+                Some(replay) if replay.ticks.is_empty() => replay.config = config,
+                _ => self.replay = None,
+            }
+        }
+        self.config = config;
+        let config = match &self.marathon {
+            Some(marathon) => marathon.apply(config),
+            None => config,
+        };
+        self.game.set_config(config);
+    }
+
+    /// Play marathon along `curve`, or stop playing marathon if `None`.
+    pub fn set_marathon(&mut self, curve: Option<MarathonCurve>) {
+        self.marathon = curve.map(Marathon::new);
+        self.restart();
+    }
+
+    /// Send garbage to the player following `schedule`, or stop sending garbage if `None`.
+    pub fn set_garbage_schedule(&mut self, schedule: Option<GarbageSchedule>) {
+        self.garbage = schedule.map(|schedule| GarbageScheduler::new(schedule, thread_rng().gen()));
+    }
+
+    /// Start every game with `seed`, or a random seed if `None`.
+    pub fn set_seed(&mut self, seed: Option<[u8; 16]>) {
+        self.seed = seed;
+    }
+
+    /// Start every game from `position`, or from an empty board if `None`.
+    pub fn set_start_position(&mut self, position: Option<Position>) {
+        self.start_position = position;
+        self.restart();
+    }
+
+    /// The board as it is now, with the hold and queue, as a fumen.
+    pub fn board_fumen(&self) -> String {
+        fumen::encode_board(&self.game.board, self.game.falling_piece())
+    }
+
+    /// The current game, if it can be played back.
+    pub fn replay(&self) -> Option<&Replay> {
+        self.replay.as_ref()
+    }
+
+    /// Play with `input`, or with `input` once the replay being played back is over.
+    pub fn set_input(&mut self, input: Box<dyn InputSource>) {
+        match &mut self.user_input {
+            Some(user_input) => *user_input = input,
+            None => self.input = input,
+        }
+    }
+
+    /// Watch `replay` being played back, until it ends or the game is restarted.
+    pub fn play_replay(&mut self, replay: Replay) {
+        let input = std::mem::replace(&mut self.input, Box::new(ReplayInput::new(replay.ticks)));
+        // another replay may already be playing in place of the player
+        self.user_input.get_or_insert(input);
+        self.start_position = None;
+        self.config = replay.config;
+        self.marathon = replay.marathon.map(Marathon::new);
+        self.garbage = replay.garbage.map(|schedule| GarbageScheduler::new(schedule, replay.garbage_seed));
+        self.opener = None;
+        self.start(replay.seed, replay.garbage_seed);
+    }
+
+    /// Hand control back to the player if a replay is being played back.
+    fn stop_replay(&mut self) {
+        if let Some(input) = self.user_input.take() {
+            self.input = input;
+        }
+    }
+
+    pub fn last_kick(&self) -> Option<Kick> {
+        self.game.last_kick()
+    }
+
+    pub fn attacks(&self) -> &[(u32, u32)] {
+        &self.attacks[..self.attack_count]
+    }
+
+    /// Practice the given opener, or stop practicing one if `None`.
+    pub fn set_opener(&mut self, opener: Option<Opener>) {
+        self.opener = opener.map(|opener| OpenerTrainer::new(opener, self.game.board.next_queue()));
+        self.restart();
+    }
+
+    pub fn set_coaching(&mut self, coaching: bool) {
+        self.coaching = coaching;
+        if coaching {
+            self.show_hints();
+        } else {
+            self.clear_hints();
+        }
+    }
+
+    /// Ask the bot where the current piece should go. The hints are available once it has decided.
+    fn show_hints(&mut self) {
+        self.hints.clear();
+        self.hint_search = self.game.falling_piece().map(|piece| {
+            HintSearch::start(&self.game.board, piece.kind.0, self.game.garbage_queue, HINT_COUNT)
+        });
+    }
+
+    fn clear_hints(&mut self) {
+        self.hints.clear();
+        self.hint_search = None;
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            snapshot: self.snapshot(),
+            state: self.state,
+            time: self.time,
+            attacks: self.attacks().to_vec(),
+            record: self.record(),
+        }
+    }
+
+    pub fn load_state(&mut self, save: SaveState) {
+        self.history.clear();
+        self.future.clear();
+        self.loaded_placements = save.record.placements;
+        self.attacks = save.attacks;
+        self.history.push(save.snapshot.clone());
+        self.restore(save.snapshot);
+        self.state = save.state;
+        self.time = save.time;
+    }
+
+    /// Return to the state right after the second-to-last placement.
+    ///
+    /// The very first snapshot is the start of the game, which can't be undone.
+    fn undo(&mut self) {
+        if self.history.len() < 2 {
+            return;
+        }
+        let undone = self.history.pop().unwrap();
+        self.future.push(undone);
+        let snapshot = self.history.last().unwrap().clone();
+        self.restore(snapshot);
+    }
+
+    fn redo(&mut self) {
+        if let Some(snapshot) = self.future.pop() {
+            self.history.push(snapshot.clone());
+            self.restore(snapshot);
+        }
+    }
+
+    /// Advance the game by one tick, returning what happened on the board if it was played.
+    pub fn update(
+        &mut self,
+        keys: &HashSet<Key>,
+        gamepad: Option<Gamepad<'_>>,
+        inputs: &[InputEvent],
+    ) -> Option<PlayerUpdate> {
+        let do_update = match self.state {
+            State::GameOver(0) => {
+                false
+            }
+            State::GameOver(ref mut delay) => {
+                *delay -= 1;
+                true
+            }
+            State::Starting(0) => {
+                self.state = State::Playing;
+                true
+            }
+            State::Starting(ref mut delay) => {
+                *delay -= 1;
+                false
+            }
+            State::Playing => true,
+        };
+
+        let mut result = None;
+        if do_update {
+            // kept to record what the board looked like before a placement
+            let before = self.game.falling_piece().map(|_| self.game.board.clone());
+            if let (State::Playing, Some(scheduler)) = (self.state, &mut self.garbage) {
+                self.game.garbage_queue += scheduler.update(self.game.ticks());
+            }
+
+            let controller = self.input.controller(keys, gamepad);
+            let presses = self.input.presses(inputs);
+            let events = self.game.update_with_presses(
+                controller,
+                &presses,
+                &mut self.piece_rng,
+                &mut self.garbage_rng,
+            );
+            self.input.update(&self.game.board, &events, self.game.garbage_queue);
+            if self.input.is_finished() {
+                self.stop_replay();
+            }
+            if let Some(replay) = &mut self.replay {
+                replay.ticks.push(ReplayTick { controller, presses });
+            }
+            for event in &events {
+                if let Event::GarbageSent(lines) = event {
+                    // undone attacks are replaced by the ones sent instead
+                    self.attacks.truncate(self.attack_count);
+                    // garbage would arrive at the opponent on the next tick
+                    self.attacks.push((self.game.ticks(), *lines));
+                    self.attack_count += 1;
+                }
+            }
+            if let Some(marathon) = &mut self.marathon {
+                let mut level_up = false;
+                for event in &events {
+                    level_up |= marathon.update(event);
+                }
+                if level_up {
+                    self.game.set_config(marathon.apply(*self.game.config()));
+                }
+            }
+
+            let update = PlayerUpdate {
+                events,
+                garbage_queue: self.game.garbage_queue,
+            };
+
+            if let State::Playing = self.state {
+                for event in &update.events {
+                    use crate::game::Event::*;
+                    match event {
+                        GameOver(_) => {
+                            self.state = State::GameOver(300);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            if let Some(trainer) = &mut self.opener {
+                for event in &update.events {
+                    trainer.update(event);
+                }
+                if self.opener_restart.is_none() && !matches!(trainer.status(), OpenerStatus::Building) {
+                    self.opener_restart = Some(OPENER_RESTART_TIME);
+                }
+            }
+
+            let placed = update.events.iter().find_map(|e| match e {
+                Event::PiecePlaced { piece, .. } => Some(*piece),
+                _ => None,
+            });
+            if let Some(piece) = placed {
+                self.future.clear();
+                let mut snapshot = self.snapshot();
+                snapshot.placement = before.map(|board| RecordedPlacement { board, piece });
+                self.history.push(snapshot);
+                self.clear_hints();
+            }
+
+            let new_piece = update.events.iter()
+                .any(|e| matches!(e, Event::PieceSpawned { .. } | Event::PieceHeld(_)));
+            if self.coaching && new_piece {
+                self.show_hints();
+            }
+
+            self.time += 1;
+            self.events.extend(update.events.iter().cloned());
+            result = Some(update);
+        }
+
+        if let Some(hints) = self.hint_search.as_ref().and_then(HintSearch::poll) {
+            self.hint_search = None;
+            self.hints = hints;
+        }
+
+        match self.opener_restart {
+            Some(0) => self.restart(),
+            Some(ref mut delay) => *delay -= 1,
+            None => {}
+        }
+
+        // the player can still restart or undo while watching a replay
+        let actions = self.user_input.as_ref().unwrap_or(&self.input).actions(keys, gamepad);
+        if actions.undo && !self.prev_actions.undo {
+            self.undo();
+        } else if actions.redo && !self.prev_actions.redo {
+            self.redo();
+        } else if actions.hint && !self.prev_actions.hint {
+            self.show_hints();
+        }
+        self.prev_actions = actions;
+
+        if actions.reset {
+            match self.reset_countdown {
+                x if x <= 0. => {
+                    self.reset_countdown = 1.;
+                    self.restart();
+                },
+                _ => self.reset_countdown -= 1. / RESET_TIME as f32,
+            }
+        } else {
+            self.reset_countdown = 1.;
+        }
+
+        result
+    }
+
+    /// A summary of the game, once it's over.
+    pub fn results(&self) -> Option<Vec<String>> {
+        let State::GameOver(0) = self.state else { return None; };
+        let pieces = self.record().placements.len();
+        let seconds = self.time as f32 / 60.;
+        let mut results = vec![
+            format!("{pieces} pieces in {}:{:04.1}", (seconds / 60.) as u32, seconds % 60.),
+            format!("{:.2} pieces per second", pieces as f32 / seconds.max(1.)),
+        ];
+        if let Some(marathon) = &self.marathon {
+            results.push(format!("Level {}", marathon.level()));
+        }
+        if let Some(reason) = self.game.game_over_reason() {
+            results.push(reason.to_string());
+        }
+        Some(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::UserInput;
+
+    /// A game with its first piece spawned.
+    fn new_game() -> Singleplayer {
+        let mut game = Singleplayer::new(Box::new(UserInput::default()));
+        game.set_seed(Some([3; 16]));
+        game.restart();
+        game.update(&HashSet::new(), None, &[]);
+        game
+    }
+
+    /// Hold down `keys` for a tick, then let go of them for a tick.
+    fn press(game: &mut Singleplayer, keys: &[Key]) {
+        game.update(&keys.iter().copied().collect(), None, &[]);
+        game.update(&HashSet::new(), None, &[]);
+    }
+
+    fn hard_drop(game: &mut Singleplayer) {
+        press(game, &[UserInput::default().keyboard.hard_drop]);
+    }
+
+    fn undo(game: &mut Singleplayer) {
+        press(game, &[UserInput::default().keyboard.undo]);
+    }
+
+    fn redo(game: &mut Singleplayer) {
+        press(game, &[UserInput::default().keyboard.redo]);
+    }
+
+    #[test]
+    fn undo_and_redo_move_between_placements() {
+        let mut game = new_game();
+        hard_drop(&mut game);
+        let first = game.board_fumen();
+        hard_drop(&mut game);
+        let second = game.board_fumen();
+        assert_ne!(first, second);
+
+        undo(&mut game);
+        assert_eq!(game.board_fumen(), first);
+        assert_eq!(game.record().placements.len(), 1);
+
+        redo(&mut game);
+        assert_eq!(game.board_fumen(), second);
+        assert_eq!(game.record().placements.len(), 2);
+    }
+
+    #[test]
+    fn placing_after_undo_forgets_what_was_undone() {
+        let mut game = new_game();
+        hard_drop(&mut game);
+        hard_drop(&mut game);
+        undo(&mut game);
+        hard_drop(&mut game);
+        let placed = game.board_fumen();
+
+        redo(&mut game);
+        assert_eq!(game.board_fumen(), placed);
+        assert_eq!(game.record().placements.len(), 2);
+    }
+
+    #[test]
+    fn the_start_of_the_game_cant_be_undone() {
+        let mut game = new_game();
+        let start = game.board_fumen();
+        undo(&mut game);
+        assert_eq!(game.board_fumen(), start);
+        assert!(game.record().placements.is_empty());
+    }
+
+    #[test]
+    fn changing_the_rules_mid_game_stops_the_replay() {
+        let mut game = Singleplayer::new(Box::new(UserInput::default()));
+        game.set_seed(Some([3; 16]));
+        game.restart();
+        let config = GameConfig { lock_delay: 20, ..GameConfig::fast_config() };
+        game.set_config(config);
+        assert_eq!(game.replay().map(|replay| replay.config), Some(config));
+
+        game.update(&HashSet::new(), None, &[]);
+        game.set_config(config);
+        assert!(game.replay().is_some());
+        game.set_config(GameConfig { lock_delay: 40, ..config });
+        assert!(game.replay().is_none());
+    }
+}
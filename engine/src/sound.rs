@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use libtetris::TspinStatus;
+use rodio::{OutputStream, OutputStreamHandle, Source};
+use serde::{Deserialize, Serialize};
+
+use crate::config::APP_NAME;
+use crate::game::Event;
+
+/// A sound effect, played for some game event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Sound {
+    Move,
+    Rotate,
+    /// A rotation that makes a t-spin.
+    Spin,
+    Hold,
+    /// A piece locked without clearing lines.
+    Lock,
+    Single,
+    Double,
+    Triple,
+    Tetris,
+    /// Lines cleared with a t-spin.
+    SpinClear,
+    PerfectClear,
+    Garbage,
+    GameOver,
+}
+
+impl Sound {
+    pub const ALL: [Sound; 13] = [
+        Sound::Move,
+        Sound::Rotate,
+        Sound::Spin,
+        Sound::Hold,
+        Sound::Lock,
+        Sound::Single,
+        Sound::Double,
+        Sound::Triple,
+        Sound::Tetris,
+        Sound::SpinClear,
+        Sound::PerfectClear,
+        Sound::Garbage,
+        Sound::GameOver,
+    ];
+
+    /// The name of the file the sound is read from, without its extension.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Sound::Move => "move",
+            Sound::Rotate => "rotate",
+            Sound::Spin => "spin",
+            Sound::Hold => "hold",
+            Sound::Lock => "lock",
+            Sound::Single => "single",
+            Sound::Double => "double",
+            Sound::Triple => "triple",
+            Sound::Tetris => "tetris",
+            Sound::SpinClear => "spin_clear",
+            Sound::PerfectClear => "perfect_clear",
+            Sound::Garbage => "garbage",
+            Sound::GameOver => "game_over",
+        }
+    }
+
+    /// The sound played for `event`, if any.
+    pub fn for_event(event: &Event) -> Option<Sound> {
+        Some(match event {
+            Event::PieceMoved => Sound::Move,
+            Event::PieceRotated => Sound::Rotate,
+            Event::PieceTSpun => Sound::Spin,
+            Event::PieceHeld(_) => Sound::Hold,
+            Event::PiecePlaced { piece, locked, .. } => match locked.cleared_lines.len() {
+                _ if locked.perfect_clear => Sound::PerfectClear,
+                0 => Sound::Lock,
+                _ if piece.tspin != TspinStatus::None => Sound::SpinClear,
+                1 => Sound::Single,
+                2 => Sound::Double,
+                3 => Sound::Triple,
+                _ => Sound::Tetris,
+            },
+            Event::GarbageAdded(_) => Sound::Garbage,
+            Event::GameOver(_) => Sound::GameOver,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoundSettings {
+    /// From 0 to 1.
+    pub volume: f32,
+    pub muted: Vec<Sound>,
+    /// Where sound files are read from, instead of the default directory.
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {
+            volume: 0.5,
+            muted: vec![],
+            directory: None,
+        }
+    }
+}
+
+impl SoundSettings {
+    /// The directory sounds are read from: a `<sound>.wav` or `<sound>.ogg` file for every sound,
+    /// see [`Sound::file_name`]. Sounds without a file aren't played.
+    pub fn directory(&self) -> Option<PathBuf> {
+        self.directory.clone()
+            .or_else(|| dirs::config_dir().map(|d| d.join(APP_NAME).join("sounds")))
+    }
+}
+
+pub trait AudioBackend {
+    /// Play `sound` at `volume`, from 0 to 1.
+    fn play(&mut self, sound: Sound, volume: f32);
+}
+
+/// Plays nothing, for when there's no audio device.
+pub struct NullBackend;
+
+impl AudioBackend for NullBackend {
+    fn play(&mut self, _: Sound, _: f32) {}
+}
+
+/// Plays sounds on the default audio device.
+pub struct RodioBackend {
+    // sounds stop playing once the stream is dropped
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+    sounds: HashMap<Sound, Arc<[u8]>>,
+}
+
+impl RodioBackend {
+    pub fn new(directory: &Path) -> Result<Self, String> {
+        let (stream, handle) = OutputStream::try_default().map_err(|e| e.to_string())?;
+        let sounds = Sound::ALL.into_iter()
+            .filter_map(|sound| {
+                let data = ["wav", "ogg"].into_iter()
+                    .find_map(|ext| std::fs::read(directory.join(sound.file_name()).with_extension(ext)).ok())?;
+                Some((sound, data.into()))
+            })
+            .collect::<HashMap<_, _>>();
+        log::info!("Loaded {} sounds from {directory:?}", sounds.len());
+        Ok(Self { _stream: stream, handle, sounds })
+    }
+}
+
+impl AudioBackend for RodioBackend {
+    fn play(&mut self, sound: Sound, volume: f32) {
+        let Some(data) = self.sounds.get(&sound) else { return; };
+        let source = match rodio::Decoder::new(Cursor::new(data.clone())) {
+            Ok(source) => source,
+            Err(e) => {
+                log::error!("Failed to decode sound {}: {e}", sound.file_name());
+                self.sounds.remove(&sound);
+                return;
+            }
+        };
+        if let Err(e) = self.handle.play_raw(source.convert_samples().amplify(volume)) {
+            log::error!("Failed to play sound {}: {e}", sound.file_name());
+        }
+    }
+}
+
+pub struct SoundPlayer {
+    backend: Box<dyn AudioBackend>,
+}
+
+impl SoundPlayer {
+    /// Play sounds on the default audio device, or nothing if there is none.
+    pub fn new(settings: &SoundSettings) -> Self {
+        let backend: Box<dyn AudioBackend> = match settings.directory().map(|dir| RodioBackend::new(&dir)) {
+            Some(Ok(backend)) => Box::new(backend),
+            Some(Err(e)) => {
+                log::error!("Failed to open audio device, playing without sound: {e}");
+                Box::new(NullBackend)
+            }
+            None => Box::new(NullBackend),
+        };
+        Self { backend }
+    }
+
+    /// Play no sound at all.
+    pub fn silent() -> Self {
+        Self { backend: Box::new(NullBackend) }
+    }
+
+    /// Play the sounds for `events` that aren't muted.
+    pub fn play(&mut self, events: &[Event], settings: &SoundSettings) {
+        if settings.volume <= 0. {
+            return;
+        }
+        for sound in events.iter().filter_map(Sound::for_event) {
+            if !settings.muted.contains(&sound) {
+                self.backend.play(sound, settings.volume);
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+
+use crate::menu::Mode;
+
+pub const USAGE: &str = "\
+Usage:
+    practris [OPTIONS]
+    practris headless (--replay <FILE> | --bot [--pieces <N>]) [OPTIONS]
+    practris headless --analyse (--replay <FILE> | --state <FILE>)
+
+Options:
+    --mode <MODE>        Start playing right away: practice, marathon, versus or bot
+    --seed <SEED>        Seed the piece order of singleplayer games with a number
+    --profile <NAME>     Use the settings of the named profile
+    --config <FILE>      Read and write settings from this file instead
+    --replay <FILE>      Watch a replay, or simulate it when headless
+    --bot                Let the bot play when headless
+    --pieces <N>         How many pieces the bot places when headless [default: 100]
+    --analyse            Compare every placement of a game to the bot's when headless
+    --state <FILE>       A saved game state to analyse
+    --mute               Play without sound
+    -h, --help           Print this message
+
+headless prints the statistics of the game as JSON once it ends, or its analysis with --analyse.";
+
+/// The command line arguments practris was started with.
+#[derive(Debug, Clone)]
+pub struct Args {
+    /// Run a game without opening a window.
+    pub headless: bool,
+    pub mode: Option<Mode>,
+    pub seed: Option<u64>,
+    pub profile: Option<String>,
+    pub config: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub bot: bool,
+    pub pieces: u32,
+    /// Print the analysis of a game instead of its statistics when headless.
+    pub analyse: bool,
+    pub state: Option<PathBuf>,
+    pub mute: bool,
+    pub help: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            headless: false,
+            mode: None,
+            seed: None,
+            profile: None,
+            config: None,
+            replay: None,
+            bot: false,
+            pieces: 100,
+            analyse: false,
+            state: None,
+            mute: false,
+            help: false,
+        }
+    }
+}
+
+impl Args {
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse(args: impl IntoIterator<Item=String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter().peekable();
+        if let Some("headless" | "--headless") = args.peek().map(String::as_str) {
+            parsed.headless = true;
+            args.next();
+        }
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--mode" => parsed.mode = Some(match value()?.as_str() {
+                    "practice" => Mode::Practice,
+                    "marathon" => Mode::Marathon,
+                    "versus" => Mode::Versus,
+                    "bot" => Mode::VersusBot,
+                    mode => return Err(format!("Unknown mode {mode}")),
+                }),
+                "--seed" => parsed.seed = Some(value()?.parse().map_err(|e| format!("Invalid seed: {e}"))?),
+                "--profile" => parsed.profile = Some(value()?),
+                "--config" => parsed.config = Some(value()?.into()),
+                "--replay" => parsed.replay = Some(value()?.into()),
+                "--pieces" => parsed.pieces = value()?.parse().map_err(|e| format!("Invalid piece count: {e}"))?,
+                "--state" => parsed.state = Some(value()?.into()),
+                "--bot" => parsed.bot = true,
+                "--analyse" => parsed.analyse = true,
+                "--mute" => parsed.mute = true,
+                "-h" | "--help" => parsed.help = true,
+                arg => return Err(format!("Unexpected argument {arg}")),
+            }
+        }
+
+        if (parsed.analyse || parsed.state.is_some()) && !parsed.headless {
+            return Err("games can only be analysed headless".to_string());
+        }
+        if parsed.analyse && parsed.replay.is_none() && parsed.state.is_none() && !parsed.help {
+            return Err("--analyse needs either --replay or --state".to_string());
+        }
+        if parsed.state.is_some() && !parsed.analyse {
+            return Err("--state is only used with --analyse".to_string());
+        }
+        if parsed.headless && parsed.replay.is_none() && !parsed.bot && !parsed.analyse && !parsed.help {
+            return Err("headless needs either --replay or --bot".to_string());
+        }
+        Ok(parsed)
+    }
+
+    /// The seed to start singleplayer games with, if one was given.
+    pub fn seed(&self) -> Option<[u8; 16]> {
+        self.seed.map(|seed| {
+            let mut bytes = [0; 16];
+            bytes[..8].copy_from_slice(&seed.to_le_bytes());
+            bytes
+        })
+    }
+}
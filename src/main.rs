@@ -5,40 +5,124 @@ use std::collections::HashSet;
 
 use gilrs::Gamepad;
 use nannou::prelude::*;
+use practris_engine::{
+    analysis, attack, bot, config, fumen, game, garbage, headless, input, marathon, opener, replay,
+    rotation, sound,
+};
 
+use crate::cli::Args;
+use crate::input::InputEvent;
 use crate::model::Model;
+use crate::ui::theme::Theme;
 
 mod model;
-mod game;
-mod input;
 mod singleplayer;
 mod util;
-mod config;
+mod versus;
+mod net;
+mod spectate;
+mod menu;
+mod cli;
 
 mod ui {
     pub use game_ui::*;
 
     mod game_ui;
     pub mod skin;
+    pub mod analysis;
+    pub mod theme;
 }
 
 trait State {
-    fn update(&mut self, keys: &HashSet<Key>, gamepad: Option<Gamepad<'_>>);
-    fn render(&self, draw: &Draw, rect: Rect);
+    /// `inputs` are the keys and buttons pressed and released since the last tick, in order.
+    fn update(&mut self, keys: &HashSet<Key>, gamepad: Option<Gamepad<'_>>, inputs: &[InputEvent]);
+    fn render(&self, draw: &Draw, rect: Rect, theme: &Theme);
+    /// Start the game over, if it can be.
+    fn restart(&mut self) {}
+    /// A summary of the game once it has ended.
+    fn results(&self) -> Option<Vec<String>> {
+        None
+    }
+    /// What happened to the local player since this was last called, to play sounds for.
+    fn take_events(&mut self) -> Vec<game::Event> {
+        vec![]
+    }
 }
 
 fn main() {
     env_logger::init();
 
+    let args = match Args::from_env() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    if args.headless {
+        match run_headless(&args) {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     nannou::app(model)
         .update(update)
         .run();
 }
 
-fn update(_app: &nannou::App, model: &mut Model, update: Update) {
-    model.update(update);
+/// Run the game `args` ask for without a window, and return what to print.
+fn run_headless(args: &Args) -> Result<String, String> {
+    if args.analyse {
+        let record = match (&args.replay, &args.state) {
+            (Some(path), _) => headless::record_replay(replay::read_replay(path)?),
+            (None, Some(path)) => practris_engine::singleplayer::read_state(path)?.record().clone(),
+            (None, None) => return Err("Nothing to analyse".to_string()),
+        };
+        let summaries: Vec<_> = analysis::analyse(&record).iter()
+            .map(analysis::PlacementAnalysis::summary)
+            .collect();
+        return Ok(serde_json::to_string_pretty(&summaries).unwrap());
+    }
+    if let Some(path) = &args.replay {
+        let replay = replay::read_replay(path)?;
+        return Ok(serde_json::to_string_pretty(&headless::run_replay(replay)).unwrap());
+    }
+
+    let settings = args.config.clone()
+        .or_else(|| config::settings_path(args.profile.as_deref()))
+        .map(|path| config::load(&path))
+        .unwrap_or_default();
+    let marathon = match args.mode {
+        Some(menu::Mode::Marathon) => Some(settings.marathon),
+        _ => None,
+    };
+    let stats = headless::run_bot(
+        settings.game,
+        marathon,
+        Default::default(),
+        args.seed(),
+        args.pieces,
+    );
+    Ok(serde_json::to_string_pretty(&stats).unwrap())
+}
+
+fn update(app: &nannou::App, model: &mut Model, update: Update) {
+    model.update(app, update);
+    if model.should_quit() {
+        app.quit();
+    }
 }
 
 fn model(app: &nannou::App) -> Model {
-    Model::from_app(app)
+    // arguments were already checked in main
+    Model::from_app(app, Args::from_env().unwrap_or_default())
 }
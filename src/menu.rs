@@ -0,0 +1,257 @@
+use gilrs::Button;
+use nannou::prelude::*;
+use nannou::text::FontSize;
+
+use crate::bot::BotConfig;
+use crate::input::{InputEvent, RawInput};
+
+/// A screen of the menu, drawn over the game.
+pub enum Screen {
+    MainMenu,
+    ModeSelect,
+    Pause,
+    /// A summary of the game that just ended.
+    Results(Vec<String>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Practice,
+    Marathon,
+    Versus,
+    VersusBot,
+}
+
+/// Something chosen in the menu that the rest of the app has to act on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MenuAction {
+    Play(Mode),
+    Retry,
+    /// Leave the game that's being played for the main menu.
+    MainMenu,
+    Settings,
+    Quit,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Item {
+    Play,
+    Settings,
+    Quit,
+    Mode(Mode),
+    Resume,
+    Retry,
+    MainMenu,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Nav {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+}
+
+/// The settings of modes that can be changed from mode select.
+pub struct ModeOptions<'a> {
+    pub first_to: &'a mut u32,
+    pub bot: &'a mut BotConfig,
+}
+
+/// A stack of screens, the last of which is shown. The game is paused while any screen is open.
+pub struct Menu {
+    /// Every open screen, with the index of its selected item.
+    screens: Vec<(Screen, usize)>,
+}
+
+impl Menu {
+    pub fn new() -> Self {
+        Self {
+            screens: vec![(Screen::MainMenu, 0)],
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.screens.is_empty()
+    }
+
+    pub fn push(&mut self, screen: Screen) {
+        self.screens.push((screen, 0));
+    }
+
+    pub fn close(&mut self) {
+        self.screens.clear();
+    }
+
+    /// Close every screen but the main menu.
+    pub fn main_menu(&mut self) {
+        self.screens = vec![(Screen::MainMenu, 0)];
+    }
+
+    /// Whether `inputs` open the pause menu.
+    pub fn pause_pressed(inputs: &[InputEvent]) -> bool {
+        inputs.iter().any(|input| input.pressed && matches!(
+            input.input,
+            RawInput::Key(Key::Escape) | RawInput::Gamepad(Button::Start)
+        ))
+    }
+
+    /// Navigate through the open screens with the keyboard or a gamepad.
+    pub fn update(&mut self, inputs: &[InputEvent], mut options: ModeOptions<'_>) -> Option<MenuAction> {
+        let mut action = None;
+        for input in inputs.iter().filter(|input| input.pressed) {
+            if let Some(nav) = nav(input.input) {
+                action = action.or(self.navigate(nav, &mut options));
+            }
+        }
+        action
+    }
+
+    fn navigate(&mut self, nav: Nav, options: &mut ModeOptions<'_>) -> Option<MenuAction> {
+        let items = match self.screens.last() {
+            Some((screen, _)) => items(screen, *options.first_to, options.bot),
+            None => return None,
+        };
+        let (screen, selected) = self.screens.last_mut()?;
+        let item = items[*selected].1;
+
+        match nav {
+            Nav::Up => *selected = (*selected + items.len() - 1) % items.len(),
+            Nav::Down => *selected = (*selected + 1) % items.len(),
+            Nav::Left | Nav::Right => {
+                let delta = if nav == Nav::Left { -1 } else { 1 };
+                match item {
+                    Item::Mode(Mode::Versus) => {
+                        *options.first_to = (*options.first_to as i32 + delta).clamp(1, 10) as u32;
+                    }
+                    Item::Mode(Mode::VersusBot) => {
+                        options.bot.pps = (options.bot.pps + 0.5 * delta as f32).clamp(0.5, 5.0);
+                    }
+                    _ => {}
+                }
+            }
+            Nav::Back => match screen {
+                Screen::MainMenu => {}
+                Screen::ModeSelect | Screen::Pause => {
+                    self.screens.pop();
+                }
+                Screen::Results(_) => {
+                    self.main_menu();
+                    return Some(MenuAction::MainMenu);
+                }
+            },
+            Nav::Select => match item {
+                Item::Play => self.push(Screen::ModeSelect),
+                Item::Settings => return Some(MenuAction::Settings),
+                Item::Quit => return Some(MenuAction::Quit),
+                Item::Mode(mode) => {
+                    self.screens.clear();
+                    return Some(MenuAction::Play(mode));
+                }
+                Item::Resume => {
+                    self.screens.pop();
+                }
+                Item::Retry => {
+                    self.screens.clear();
+                    return Some(MenuAction::Retry);
+                }
+                Item::MainMenu => {
+                    self.main_menu();
+                    return Some(MenuAction::MainMenu);
+                }
+            },
+        }
+        None
+    }
+
+    pub fn render(&self, draw: &Draw, rect: Rect, first_to: u32, bot: &BotConfig) {
+        let Some((screen, selected)) = self.screens.last() else { return; };
+
+        draw.rect()
+            .xy(rect.xy())
+            .wh(rect.wh())
+            .color(Rgba::new(0., 0., 0., 0.8));
+
+        let font_size = (rect.w() / 60.) as FontSize;
+        let line_height = font_size as f32 * 2.;
+        let title = match screen {
+            Screen::MainMenu => "PRACTRIS",
+            Screen::ModeSelect => "SELECT MODE",
+            Screen::Pause => "PAUSED",
+            Screen::Results(_) => "RESULTS",
+        };
+        let mut y = rect.y() + rect.h() / 4.;
+        draw.text(title)
+            .font_size(font_size * 2)
+            .w(rect.w())
+            .x_y(rect.x(), y)
+            .center_justify();
+        y -= line_height * 2.;
+
+        if let Screen::Results(lines) = screen {
+            for line in lines {
+                draw.text(line)
+                    .font_size(font_size)
+                    .w(rect.w())
+                    .x_y(rect.x(), y)
+                    .center_justify();
+                y -= line_height;
+            }
+            y -= line_height;
+        }
+
+        for (idx, (label, _)) in items(screen, first_to, bot).iter().enumerate() {
+            let (label, color) = if idx == *selected {
+                (format!("> {label} <"), YELLOW)
+            } else {
+                (label.clone(), WHITE)
+            };
+            draw.text(&label)
+                .font_size(font_size)
+                .w(rect.w())
+                .x_y(rect.x(), y)
+                .color(color)
+                .center_justify();
+            y -= line_height;
+        }
+    }
+}
+
+fn items(screen: &Screen, first_to: u32, bot: &BotConfig) -> Vec<(String, Item)> {
+    match screen {
+        Screen::MainMenu => vec![
+            ("Play".to_string(), Item::Play),
+            ("Settings".to_string(), Item::Settings),
+            ("Quit".to_string(), Item::Quit),
+        ],
+        Screen::ModeSelect => vec![
+            ("Practice".to_string(), Item::Mode(Mode::Practice)),
+            ("Marathon".to_string(), Item::Mode(Mode::Marathon)),
+            (format!("Versus - first to ◀ {first_to} ▶"), Item::Mode(Mode::Versus)),
+            (format!("Versus bot - ◀ {:.1} PPS ▶", bot.pps), Item::Mode(Mode::VersusBot)),
+        ],
+        Screen::Pause => vec![
+            ("Resume".to_string(), Item::Resume),
+            ("Retry".to_string(), Item::Retry),
+            ("Quit to menu".to_string(), Item::MainMenu),
+        ],
+        Screen::Results(_) => vec![
+            ("Retry".to_string(), Item::Retry),
+            ("Main menu".to_string(), Item::MainMenu),
+        ],
+    }
+}
+
+fn nav(input: RawInput) -> Option<Nav> {
+    Some(match input {
+        RawInput::Key(Key::Up) | RawInput::Gamepad(Button::DPadUp) => Nav::Up,
+        RawInput::Key(Key::Down) | RawInput::Gamepad(Button::DPadDown) => Nav::Down,
+        RawInput::Key(Key::Left) | RawInput::Gamepad(Button::DPadLeft) => Nav::Left,
+        RawInput::Key(Key::Right) | RawInput::Gamepad(Button::DPadRight) => Nav::Right,
+        RawInput::Key(Key::Return | Key::Space) | RawInput::Gamepad(Button::South) => Nav::Select,
+        RawInput::Key(Key::Escape) | RawInput::Gamepad(Button::East | Button::Start) => Nav::Back,
+        _ => return None,
+    })
+}
@@ -1,35 +1,111 @@
 use std::collections::HashSet;
-use std::path::PathBuf;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
 use gilrs::{EventType, GamepadId, Gilrs};
 use nannou::prelude::*;
 use nannou::text::FontSize;
 use nannou_egui::{egui, Egui};
-use crate::config::UserSettings;
-use crate::input::{Config, UserInput};
+use practris_engine::singleplayer::{read_state, write_state};
+use rand::{Rng, thread_rng};
+use crate::analysis::AnalysisJob;
+use crate::attack::{AttackTable, CustomAttack};
+use crate::bot::{BotConfig, BotInput};
+use crate::cli::Args;
+use crate::config::{self, APP_NAME, SettingsFile, UserSettings};
+use crate::fumen;
+use crate::game::{GameConfig, LockReset, TopOutRules};
+use crate::garbage::{self, GarbageSchedule};
+use crate::headless;
+use crate::input::{Config, InputEvent, RawInput, UserInput};
+use crate::menu::{Menu, MenuAction, Mode, ModeOptions, Screen};
+use crate::opener::{self, Opener};
+use crate::replay::{read_replay, write_replay};
+use crate::rotation::RotationSystem;
+use crate::ui::analysis::AnalysisWindow;
+use crate::ui::theme::{self, Accessibility, Background, GhostStyle, Palette, Theme};
 
 use crate::singleplayer::SingleplayerGame;
+use crate::sound::{Sound, SoundPlayer, SoundSettings};
+use crate::net::{self, NetGame};
+use crate::spectate::{self, SpectatorGame, SpectatorServer};
+use crate::versus::{Device, Player, VersusGame};
 use crate::State;
 
-const APP_NAME: &'static str = "practris";
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
 pub struct Model {
     egui: Egui,
     ui: Ui,
     keys_pressed: HashSet<Key>,
+    /// Keys and buttons pressed and released since the last tick.
+    inputs: Vec<InputEvent>,
+    /// When the app started. Keys, gamepad buttons and ticks are all timed from it, so inputs from
+    /// either register in the order they happened.
+    started: Instant,
     game: SingleplayerGame,
+    /// Replaces the singleplayer game while another mode, such as versus, is being played.
+    mode: Option<Box<dyn State>>,
+    texture: wgpu::Texture,
     gilrs: Gilrs,
     gamepad: Option<GamepadId>,
     ui_occupation: (f32, f32),
     since_last_tick: Duration,
     settings: UserSettings,
+    openers: Vec<Opener>,
+    menu: Menu,
+    /// Set when the app should close.
+    quit: bool,
+    settings_file: Option<SettingsFile>,
+    /// When the settings file was last checked for changes.
+    last_settings_check: Duration,
+    sound: SoundPlayer,
+    theme: Theme,
+    /// The background image of the theme, and where it was read from. `None` if it couldn't be read.
+    background: Option<(PathBuf, Option<wgpu::Texture>)>,
 }
 
 struct Ui {
     settings_open: bool,
     keyboard: Option<(Config<Key>, Option<usize>)>,
+    game_state: Option<GameStateWindow>,
+    online: Option<OnlineWindow>,
+    /// The address of the game to spectate, while choosing one.
+    spectate: Option<String>,
+    analysis: Option<AnalysisWindow>,
+    /// Index into `Model::openers` of the opener being practiced.
+    opener: Option<usize>,
+    coaching: bool,
+    marathon: bool,
+    /// Whether the window showing the kick used by the last rotation is open.
+    kicks_open: bool,
+    /// The garbage sent to the player in singleplayer.
+    garbage: Option<GarbageSchedule>,
+    timeline_path: String,
+    /// How many rounds have to be won to win a versus match.
+    first_to: u32,
+    bot: BotConfig,
+    /// Whether the singleplayer game is being streamed to spectators.
+    streaming: bool,
+    stream_port: u16,
+    /// The name to save the theme as.
+    theme_name: String,
+}
+
+/// The window used to save the running game to, or load it from, a file.
+struct GameStateWindow {
+    path: String,
+    /// Where the replay of the running game is saved.
+    replay_path: String,
+    /// A fumen exported from, or to be imported into, the running game.
+    fumen: String,
+    status: Option<String>,
+}
+
+/// The window used to host or join an online match.
+struct OnlineWindow {
+    address: String,
+    input_delay: u64,
 }
 
 impl Default for Ui {
@@ -37,13 +113,31 @@ impl Default for Ui {
         Self {
             settings_open: false,
             keyboard: None,
+            game_state: None,
+            online: None,
+            spectate: None,
+            analysis: None,
+            opener: None,
+            coaching: false,
+            marathon: false,
+            kicks_open: false,
+            garbage: None,
+            timeline_path: get_timeline_file()
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            first_to: 3,
+            bot: BotConfig::default(),
+            streaming: false,
+            stream_port: spectate::DEFAULT_PORT,
+            theme_name: String::new(),
         }
     }
 }
 
 impl Ui {
     pub fn is_paused(&self) -> bool {
-        self.settings_open || self.keyboard.is_some()
+        self.settings_open || self.keyboard.is_some() || self.game_state.is_some() || self.online.is_some()
+            || self.spectate.is_some() || self.analysis.is_some()
     }
 }
 
@@ -52,43 +146,143 @@ impl Model {
     /// In a correctly timed environment, this is done exactly 60 times per second.
     /// Some games may choose to rely on the FPS to be consistently 60, and thus tick when the game is rendered.
     /// practris allows itself more than 60 fps, thus we separate this logic and only tick when necessary.
-    fn tick(&mut self) {
-        self.update_gamepad();
-        self.game.update(&self.keys_pressed, self.gamepad.map(|id| self.gilrs.gamepad(id)));
+    ///
+    /// The tick receives the inputs that happened up to `end`.
+    fn tick(&mut self, end: Duration) {
+        let count = self.inputs.iter().take_while(|input| input.time <= end).count();
+        let inputs: Vec<_> = self.inputs.drain(..count).collect();
+        let gamepad = self.gamepad.map(|id| self.gilrs.gamepad(id));
+        let state: &mut dyn State = match &mut self.mode {
+            Some(mode) => mode.as_mut(),
+            None => &mut self.game,
+        };
+        state.update(&self.keys_pressed, gamepad, &inputs);
+        self.sound.play(&state.take_events(), &self.settings.sound);
     }
 
     /// Render the game and process a tick if applicable.
     ///
     /// Ticks may not happen when the game is rendered above 60 fps, where some frames will be rendered without a game tick being processed.
-    pub fn update(&mut self, update: Update) {
-        if !self.ui.is_paused() {
+    pub fn update(&mut self, app: &App, update: Update) {
+        self.update_gamepad();
+        self.load_background(app);
+        self.inputs.sort_by_key(|input| input.time);
+        if self.menu.is_open() {
+            let inputs = std::mem::take(&mut self.inputs);
+            let options = ModeOptions {
+                first_to: &mut self.ui.first_to,
+                bot: &mut self.ui.bot,
+            };
+            if let Some(action) = self.menu.update(&inputs, options) {
+                self.menu_action(action);
+            }
+        } else if Menu::pause_pressed(&self.inputs) {
+            self.inputs.clear();
+            self.menu.push(Screen::Pause);
+        }
+
+        if !self.ui.is_paused() && !self.menu.is_open() {
             // how many ticks should have passed since the last tick?
             const TICK_STRIDE: f32 = 1000. / 60.;
-            let diff = (update.since_start - self.since_last_tick).as_millis();
+            let now = self.started.elapsed();
+            let diff = (now - self.since_last_tick).as_millis();
             let pass = diff as f32 / TICK_STRIDE; // cast is okay: overflow only if you left practris open for longer than you or your children will live
             if pass >= 60. * 10. {
                 // the game hasn't seen a tick update in over 10s: let's not care and skip time.
                 log::info!("Skipping ticks as the game is lagging behind for >10s");
-                self.since_last_tick = update.since_start;
+                self.since_last_tick = now;
+                self.inputs.clear();
             } else if pass >= 1. {
                 let to_process = pass as usize;
+                let first_tick = self.since_last_tick;
                 self.since_last_tick = self.since_last_tick + Duration::from_millis((to_process as f32 * TICK_STRIDE) as u64);
-                for _ in 0..to_process {
-                    self.tick();
+                for tick in 1..=to_process {
+                    self.tick(first_tick + Duration::from_millis((tick as f32 * TICK_STRIDE) as u64));
                 }
             }
         } else {
-            self.since_last_tick = update.since_start;
+            self.since_last_tick = self.started.elapsed();
+            self.inputs.clear();
+        }
+
+        if !self.menu.is_open() {
+            let results = match &self.mode {
+                Some(mode) => mode.results(),
+                None => self.game.results(),
+            };
+            if let Some(results) = results {
+                self.menu.push(Screen::Results(results));
+            }
         }
 
         self.egui.set_elapsed_time(update.since_start);
         self.show_ui();
+        self.theme.accessibility = self.settings.accessibility;
+        self.sync_settings(update.since_start);
+    }
+
+    /// Save settings as soon as they change, and pick up changes made to the settings file by
+    /// something else.
+    fn sync_settings(&mut self, now: Duration) {
+        const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+        let Some(file) = &mut self.settings_file else { return; };
+        let before = self.settings.clone();
+        file.save(&mut self.settings);
+        if now - self.last_settings_check >= CHECK_INTERVAL {
+            self.last_settings_check = now;
+            file.reload(&mut self.settings);
+        }
+
+        // apply whatever was taken in from the file
+        if self.settings != before {
+            self.game.set_input(Box::new(self.settings.input));
+            self.game.set_name(self.settings.name.clone());
+            self.game.set_config(self.settings.game);
+            if self.settings.theme != before.theme {
+                let name = self.settings.theme.clone();
+                self.set_theme(&name);
+            }
+        }
+    }
+
+    /// Switch to the theme called `name`, keeping the current one if it can't be read.
+    fn set_theme(&mut self, name: &str) {
+        match theme::load(name) {
+            Ok(theme) => {
+                self.theme = theme;
+                self.settings.theme = name.to_string();
+            }
+            Err(e) => log::error!("Failed to load theme {name}: {e}"),
+        }
+    }
+
+    fn load_background(&mut self, app: &App) {
+        let path = match &self.theme.background {
+            Background::Image(path) => Some(path),
+            Background::Color(_) => None,
+        };
+        if path == self.background.as_ref().map(|(loaded, _)| loaded) {
+            return;
+        }
+        self.background = path.map(|path| {
+            let texture = wgpu::Texture::from_path(app, path)
+                .map_err(|e| log::error!("Failed to read background image {path:?}: {e}"))
+                .ok();
+            (path.clone(), texture)
+        });
     }
 
     fn show_ui(&mut self) {
         let egui = &mut self.egui;
         let frame_ctx = egui.begin_frame();
         let ctx = &frame_ctx.context();
+        if ctx.style().visuals.dark_mode != self.theme.dark_mode {
+            ctx.set_visuals(if self.theme.dark_mode {
+                egui::Visuals::dark()
+            } else {
+                egui::Visuals::light()
+            });
+        }
 
         let header_height = egui::TopBottomPanel::top("top_bar").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
@@ -99,6 +293,54 @@ impl Model {
                     if ui.selectable_label(self.ui.settings_open, "⚙ Settings").clicked() {
                         self.ui.settings_open = !self.ui.settings_open;
                     }
+                    if self.mode.is_some() && ui.button("🎮 Singleplayer").clicked() {
+                        self.mode = None;
+                    }
+                    if ui.button("⚔ Versus").clicked() {
+                        self.mode = Some(Box::new(new_versus(self.settings.input, &self.texture, self.ui.first_to, self.settings.game)));
+                    }
+                    if ui.button("🤖 Versus bot").clicked() {
+                        self.mode = Some(Box::new(new_bot_versus(self.settings.input, &self.settings.name, self.ui.bot, &self.texture, self.ui.first_to, self.settings.game)));
+                    }
+                    if ui.selectable_label(self.ui.online.is_some(), "🌐 Online").clicked() {
+                        self.ui.online = match self.ui.online {
+                            Some(_) => None,
+                            None => Some(OnlineWindow {
+                                address: net::DEFAULT_ADDRESS.to_string(),
+                                input_delay: 3,
+                            }),
+                        };
+                    }
+                    if ui.selectable_label(self.ui.spectate.is_some(), "👁 Spectate").clicked() {
+                        self.ui.spectate = match self.ui.spectate {
+                            Some(_) => None,
+                            None => Some(format!("127.0.0.1:{}", spectate::DEFAULT_PORT)),
+                        };
+                    }
+                    if ui.selectable_label(self.ui.analysis.is_some(), "📈 Analysis").clicked() {
+                        self.ui.analysis = match self.ui.analysis {
+                            Some(_) => None,
+                            None => {
+                                let record = self.game.record();
+                                Some(AnalysisWindow::new(AnalysisJob::start(move || record)))
+                            }
+                        };
+                    }
+                    if ui.selectable_label(self.ui.game_state.is_some(), "💾 Game state").clicked() {
+                        self.ui.game_state = match self.ui.game_state {
+                            Some(_) => None,
+                            None => Some(GameStateWindow {
+                                path: get_state_file()
+                                    .map(|p| p.to_string_lossy().into_owned())
+                                    .unwrap_or_default(),
+                                replay_path: get_replay_file()
+                                    .map(|p| p.to_string_lossy().into_owned())
+                                    .unwrap_or_default(),
+                                fumen: String::new(),
+                                status: None,
+                            }),
+                        };
+                    }
                 })
             });
         }).response.rect.max.y;
@@ -113,12 +355,116 @@ impl Model {
                         ui.separator()
                     });
 
+                    ui.horizontal(|ui| {
+                        ui.label("Name");
+                        if ui.text_edit_singleline(&mut self.settings.name).changed() {
+                            self.game.set_name(self.settings.name.clone());
+                        }
+                    });
+
                     ui.add_enabled_ui(self.ui.keyboard.is_none(), |ui| {
                         if ui.button("Keyboard settings").clicked() {
                             let input = self.settings.input.clone();
                             self.ui.keyboard = Some((input.keyboard, None));
                         }
                     });
+
+                    let openers = &self.openers;
+                    let mut selected = self.ui.opener;
+                    egui::ComboBox::from_label("Opener")
+                        .selected_text(selected.map_or("None", |idx| openers[idx].name.as_str()))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut selected, None, "None");
+                            for (idx, opener) in openers.iter().enumerate() {
+                                ui.selectable_value(&mut selected, Some(idx), opener.name.as_str());
+                            }
+                        });
+                    if selected != self.ui.opener {
+                        self.ui.opener = selected;
+                        self.game.set_opener(selected.map(|idx| openers[idx].clone()));
+                    }
+
+                    if ui.checkbox(&mut self.ui.coaching, "Coaching")
+                        .on_hover_text("Always show the bot's suggested placements")
+                        .changed() {
+                        self.game.set_coaching(self.ui.coaching);
+                    }
+
+                    let irs = ui.checkbox(&mut self.settings.game.irs, "IRS")
+                        .on_hover_text("Initial rotation: rotate pieces as they spawn by holding a rotation button");
+                    let ihs = ui.checkbox(&mut self.settings.game.ihs, "IHS")
+                        .on_hover_text("Initial hold: hold pieces as they spawn by holding the hold button");
+                    let config = &mut self.settings.game;
+                    let rotation_system = rotation_system_ui(ui, &mut config.rotation_system);
+                    let spawn = spawn_ui(ui, &mut config.spawn_row, &mut config.spawn_fall, &mut config.top_out);
+                    let lock = lock_reset_ui(ui, &mut config.lock_reset, &mut config.max_lock_time);
+                    let attack = attack_table_ui(ui, &mut config.attack);
+                    if irs.changed() || ihs.changed() || rotation_system || spawn || lock || attack {
+                        self.game.set_config(self.settings.game);
+                    }
+                    ui.checkbox(&mut self.ui.kicks_open, "Show kicks")
+                        .on_hover_text("Show which kick the last rotation used");
+
+                    if ui.checkbox(&mut self.ui.marathon, "Marathon")
+                        .on_hover_text("Speed up every 10 lines, up to 20G")
+                        .changed() {
+                        let curve = self.settings.marathon.clone();
+                        self.game.set_marathon(self.ui.marathon.then(|| curve));
+                    }
+
+                    if garbage_schedule_ui(ui, &mut self.ui.garbage, &mut self.ui.timeline_path, self.game.attacks()) {
+                        self.game.set_garbage_schedule(self.ui.garbage.clone());
+                    }
+
+                    ui.add(egui::Slider::new(&mut self.ui.first_to, 1..=10).text("Versus first to"));
+                    ui.add(egui::Slider::new(&mut self.ui.bot.pps, 0.5..=5.0).text("Bot PPS"));
+                    ui.add(egui::Slider::new(&mut self.ui.bot.max_nodes, 100..=200_000)
+                        .logarithmic(true)
+                        .text("Bot strength"))
+                        .on_hover_text("How many positions the bot looks at before deciding on a move");
+
+                    let mut theme_name = self.settings.theme.clone();
+                    egui::ComboBox::from_label("Theme")
+                        .selected_text(&theme_name)
+                        .show_ui(ui, |ui| {
+                            for name in theme::names() {
+                                ui.selectable_value(&mut theme_name, name.clone(), name);
+                            }
+                        });
+                    if theme_name != self.settings.theme {
+                        self.set_theme(&theme_name);
+                    }
+                    if theme_ui(ui, &mut self.theme, &mut self.ui.theme_name) {
+                        let name = self.ui.theme_name.clone();
+                        match theme::save(&name, &self.theme) {
+                            Ok(()) => self.settings.theme = name,
+                            Err(e) => log::error!("Failed to save theme: {e}"),
+                        }
+                    }
+
+                    accessibility_ui(ui, &mut self.settings.accessibility);
+
+                    if sound_ui(ui, &mut self.settings.sound) {
+                        self.sound = SoundPlayer::new(&self.settings.sound);
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.add_enabled(
+                            !self.ui.streaming,
+                            egui::DragValue::new(&mut self.ui.stream_port).prefix("port "),
+                        );
+                        if ui.checkbox(&mut self.ui.streaming, "Allow spectators").changed() {
+                            let server = if self.ui.streaming {
+                                SpectatorServer::bind(self.ui.stream_port)
+                                    .map_err(|e| log::error!("Failed to start spectator server: {e}"))
+                                    .ok()
+                            } else {
+                                None
+                            };
+                            self.ui.streaming = server.is_some();
+                            self.game.set_spectators(server);
+                        }
+                    });
                 }).response.rect.width()
         } else {
             0.
@@ -140,6 +486,9 @@ impl Model {
                             ("Soft drop", &mut keyboard.soft_drop),
                             ("Hold", &mut keyboard.hold),
                             ("Reset", &mut keyboard.reset),
+                            ("Undo", &mut keyboard.undo),
+                            ("Redo", &mut keyboard.redo),
+                            ("Hint", &mut keyboard.hint),
                         ].into_iter().enumerate() {
                             ui.label(name);
                             // if this key is currently waiting for input,
@@ -171,13 +520,245 @@ impl Model {
                     ..Default::default()
                 };
                 log::info!("User keyboard settings changed to {:#?}", self.settings.input);
-                self.game.input = Box::new(self.settings.input.clone());
+                self.game.set_input(Box::new(self.settings.input.clone()));
+            }
+        }
+
+        if let Some(window) = &mut self.ui.game_state {
+            let mut open = true;
+            egui::Window::new("Game state")
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("File");
+                        ui.text_edit_singleline(&mut window.path);
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Save").clicked() {
+                            let state = self.game.save_state();
+                            window.status = Some(match write_state(Path::new(&window.path), &state) {
+                                Ok(()) => "Saved".to_string(),
+                                Err(e) => {
+                                    log::error!("Failed to save game state: {e}");
+                                    format!("Failed to save: {e}")
+                                }
+                            });
+                        }
+                        if ui.button("Load").clicked() {
+                            window.status = Some(match read_state(Path::new(&window.path)) {
+                                Ok(state) => {
+                                    self.game.load_state(state);
+                                    "Loaded".to_string()
+                                }
+                                Err(e) => {
+                                    log::error!("Failed to load game state: {e}");
+                                    format!("Failed to load: {e}")
+                                }
+                            });
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Replay");
+                        ui.text_edit_singleline(&mut window.replay_path);
+                    });
+                    ui.horizontal(|ui| {
+                        let replay = self.game.replay();
+                        let save = ui.add_enabled(replay.is_some(), egui::Button::new("Save replay"))
+                            .on_disabled_hover_text("Games can't be replayed after undoing or loading a state");
+                        if let (true, Some(replay)) = (save.clicked(), replay) {
+                            window.status = Some(match write_replay(Path::new(&window.replay_path), replay) {
+                                Ok(()) => "Saved replay".to_string(),
+                                Err(e) => {
+                                    log::error!("Failed to save replay: {e}");
+                                    format!("Failed to save replay: {e}")
+                                }
+                            });
+                        }
+                        if ui.button("Watch replay").clicked() {
+                            window.status = Some(match read_replay(Path::new(&window.replay_path)) {
+                                Ok(replay) => {
+                                    self.game.play_replay(replay);
+                                    "Playing replay".to_string()
+                                }
+                                Err(e) => {
+                                    log::error!("Failed to load replay: {e}");
+                                    format!("Failed to load replay: {e}")
+                                }
+                            });
+                        }
+                        if ui.button("Analyse replay").clicked() {
+                            window.status = Some(match read_replay(Path::new(&window.replay_path)) {
+                                Ok(replay) => {
+                                    let job = AnalysisJob::start(move || headless::record_replay(replay));
+                                    self.ui.analysis = Some(AnalysisWindow::new(job));
+                                    "Analysing replay".to_string()
+                                }
+                                Err(e) => {
+                                    log::error!("Failed to load replay: {e}");
+                                    format!("Failed to load replay: {e}")
+                                }
+                            });
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Fumen");
+                        ui.text_edit_singleline(&mut window.fumen);
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Export board").clicked() {
+                            window.fumen = self.game.board_fumen();
+                            ui.output().copied_text = window.fumen.clone();
+                            window.status = Some("Copied the board to the clipboard".to_string());
+                        }
+                        if ui.button("Export game").clicked() {
+                            window.fumen = fumen::encode_game(&self.game.record());
+                            ui.output().copied_text = window.fumen.clone();
+                            window.status = Some("Copied the game to the clipboard".to_string());
+                        }
+                        if ui.button("Start from fumen").clicked() {
+                            window.status = Some(match fumen::decode_position(&window.fumen) {
+                                Ok(position) => {
+                                    self.game.set_start_position(Some(position));
+                                    "Started from the fumen".to_string()
+                                }
+                                Err(e) => {
+                                    log::error!("Failed to import fumen: {e}");
+                                    format!("Failed to import fumen: {e}")
+                                }
+                            });
+                        }
+                        if ui.button("Empty board").clicked() {
+                            self.game.set_start_position(None);
+                            window.status = None;
+                        }
+                    });
+                    if let Some(status) = &window.status {
+                        ui.label(status);
+                    }
+                });
+
+            if !open {
+                self.ui.game_state = None;
+            }
+        }
+
+        if let Some(window) = &mut self.ui.online {
+            let mut open = true;
+            let mut started = None;
+            egui::Window::new("Online")
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Address");
+                        ui.text_edit_singleline(&mut window.address);
+                    });
+                    ui.add(egui::Slider::new(&mut window.input_delay, 0..=10).text("Input delay"))
+                        .on_hover_text("How many ticks inputs take to register, to hide latency. Decided by the host.");
+                    ui.horizontal(|ui| {
+                        if ui.button("Host").clicked() {
+                            started = Some(NetGame::host(
+                                &window.address,
+                                window.input_delay,
+                                self.settings.game,
+                                self.settings.input,
+                                self.texture.clone(),
+                            ));
+                        }
+                        if ui.button("Join").clicked() {
+                            started = Some(NetGame::join(&window.address, self.settings.input, self.texture.clone()));
+                        }
+                    });
+                });
+
+            if let Some(game) = started {
+                self.mode = Some(Box::new(game));
+                self.ui.online = None;
+            } else if !open {
+                self.ui.online = None;
+            }
+        }
+
+        if let Some(address) = &mut self.ui.spectate {
+            let mut open = true;
+            let mut connect = false;
+            egui::Window::new("Spectate")
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Address");
+                        ui.text_edit_singleline(address);
+                    });
+                    connect = ui.button("Connect").clicked();
+                });
+
+            if connect {
+                self.mode = Some(Box::new(SpectatorGame::connect(address, self.texture.clone())));
+                self.ui.spectate = None;
+            } else if !open {
+                self.ui.spectate = None;
+            }
+        }
+
+        if self.ui.kicks_open {
+            let kick = self.game.last_kick();
+            egui::Window::new("Kicks")
+                .open(&mut self.ui.kicks_open)
+                .show(ctx, |ui| match kick {
+                    Some(kick) => {
+                        ui.label(format!("{:?}: {:?} → {:?}", kick.piece, kick.from, kick.to));
+                        ui.label(format!("Test {}, offset ({}, {})", kick.index + 1, kick.offset.0, kick.offset.1));
+                    }
+                    None => {
+                        ui.label("No rotations yet");
+                    }
+                });
+        }
+
+        if let Some(window) = &mut self.ui.analysis {
+            let mut open = true;
+            window.show(ctx, &mut open);
+            if !open {
+                self.ui.analysis = None;
             }
         }
 
         self.ui_occupation = (header_height, sidebar_width);
     }
 
+    fn menu_action(&mut self, action: MenuAction) {
+        match action {
+            MenuAction::Play(Mode::Practice) => {
+                self.mode = None;
+                self.game.set_input(Box::new(self.settings.input.clone()));
+                self.ui.marathon = false;
+                self.game.set_marathon(None);
+            }
+            MenuAction::Play(Mode::Marathon) => {
+                self.mode = None;
+                self.game.set_input(Box::new(self.settings.input.clone()));
+                self.ui.marathon = true;
+                self.game.set_marathon(Some(self.settings.marathon.clone()));
+            }
+            MenuAction::Play(Mode::Versus) => {
+                self.mode = Some(Box::new(new_versus(self.settings.input, &self.texture, self.ui.first_to, self.settings.game)));
+            }
+            MenuAction::Play(Mode::VersusBot) => {
+                self.mode = Some(Box::new(new_bot_versus(self.settings.input, &self.settings.name, self.ui.bot, &self.texture, self.ui.first_to, self.settings.game)));
+            }
+            MenuAction::Retry => match &mut self.mode {
+                Some(mode) => mode.restart(),
+                None => State::restart(&mut self.game),
+            },
+            MenuAction::MainMenu => self.mode = None,
+            MenuAction::Settings => self.ui.settings_open = true,
+            MenuAction::Quit => self.quit = true,
+        }
+    }
+
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+
     fn update_gamepad(&mut self) {
         while let Some(event) = self.gilrs.next_event() {
             match event.event {
@@ -191,17 +772,41 @@ impl Model {
                         self.gamepad = None;
                     }
                 }
+                EventType::ButtonPressed(button, _) | EventType::ButtonReleased(button, _)
+                    if self.gamepad == Some(event.id) => {
+                    // gilrs timestamps events with the system clock, which isn't monotonic, so only
+                    // how long ago the event happened is taken from it
+                    let age = SystemTime::now().duration_since(event.time).unwrap_or_default();
+                    self.inputs.push(InputEvent {
+                        time: self.started.elapsed().saturating_sub(age),
+                        input: RawInput::Gamepad(button),
+                        pressed: matches!(event.event, EventType::ButtonPressed(..)),
+                    });
+                }
                 _ => {}
             }
         }
     }
 
     fn key_pressed(_app: &App, model: &mut Self, key: Key) {
-        model.keys_pressed.insert(key);
+        // key repeat presses keys that are already down
+        if model.keys_pressed.insert(key) {
+            model.inputs.push(InputEvent {
+                time: model.started.elapsed(),
+                input: RawInput::Key(key),
+                pressed: true,
+            });
+        }
     }
 
     fn key_released(_app: &App, model: &mut Self, key: Key) {
-        model.keys_pressed.remove(&key);
+        if model.keys_pressed.remove(&key) {
+            model.inputs.push(InputEvent {
+                time: model.started.elapsed(),
+                input: RawInput::Key(key),
+                pressed: false,
+            });
+        }
     }
 
     fn raw_event(_app: &App, model: &mut Self, event: &nannou::winit::event::WindowEvent<'_>) {
@@ -210,7 +815,13 @@ impl Model {
 
     fn view(app: &App, model: &Self, frame: Frame<'_>) {
         let draw = app.draw();
-        draw.background().color(GREY);
+        match &model.theme.background {
+            Background::Color([r, g, b]) => draw.background().color(rgb8(*r, *g, *b)),
+            Background::Image(_) => draw.background().color(BLACK),
+        };
+        if let Some((_, Some(texture))) = &model.background {
+            draw.texture(texture).wh(app.window_rect().wh());
+        }
 
         let (header, sidebar) = model.ui_occupation;
         let window_rect = app.window_rect();
@@ -220,8 +831,13 @@ impl Model {
             window_rect.bottom_right() - Point2::new(sidebar, 0.),
         );
 
-        model.game.render(&draw, draw_space);
-        if model.ui.is_paused() {
+        match &model.mode {
+            Some(mode) => mode.render(&draw, draw_space, &model.theme),
+            None => model.game.render(&draw, draw_space, &model.theme),
+        }
+        if model.menu.is_open() {
+            model.menu.render(&draw, draw_space, model.ui.first_to, &model.ui.bot);
+        } else if model.ui.is_paused() {
             draw.rect()
                 .xy(window_rect.xy())
                 .wh(window_rect.wh())
@@ -238,24 +854,13 @@ impl Model {
     }
 
     fn closed(_: &App, model: &mut Self) {
-        if let Some(dir) = get_config_file() {
-            log::info!("Saving configuration to {dir:?}");
-            match ron::ser::to_string_pretty(&model.settings, Default::default()) {
-                Ok(cfg) => {
-                    if let Err(e) = std::fs::write(&dir, cfg) {
-                        log::error!("Failed to write configuration: {e}")
-                    }
-                }
-                Err(e) => {
-                    log::error!("Failed to serialize configuration: {e}");
-                }
-            };
-        } else {
-            log::error!("Could not get config dir in order to save configuration");
+        match &mut model.settings_file {
+            Some(file) => file.save(&mut model.settings),
+            None => log::error!("Could not get config dir in order to save configuration"),
         }
     }
 
-    pub fn from_app(app: &App) -> Self {
+    pub fn from_app(app: &App, args: Args) -> Self {
         app.set_exit_on_escape(false); // closed event not emitted on escape? it's annoying either way
         let window_id = app.new_window()
             .key_pressed(Model::key_pressed)
@@ -283,38 +888,414 @@ impl Model {
         let assets = app.assets_path().unwrap();
         let texture = wgpu::Texture::from_path(app, assets.join("skin.png")).unwrap();
 
-        let settings = if let Some(dir) = get_config_file() {
-            log::info!("Loading configuration from {dir:?}");
-            if let Ok(src) = std::fs::read_to_string(dir) {
-                ron::from_str(src.as_str())
-                    .unwrap_or_else(|e| {
-                        log::error!("Failed to read config: {e}!");
-                        UserSettings::default()
-                    })
-            } else {
-                log::info!("Configuration file not present: probably a first launch.");
+        let settings_file = args.config.clone()
+            .or_else(|| config::settings_path(args.profile.as_deref()))
+            .map(SettingsFile::open);
+        let settings = match &settings_file {
+            Some(file) => file.settings().clone(),
+            None => {
+                log::error!("Could not get config dir in order to load configuration");
                 UserSettings::default()
             }
+        };
+
+        let sound = if args.mute {
+            SoundPlayer::silent()
         } else {
-            log::error!("Could not get config dir in order to load configuration");
-            UserSettings::default()
+            SoundPlayer::new(&settings.sound)
         };
 
-        Self {
+        let mut model = Self {
             egui,
             ui: Ui::default(),
             keys_pressed: HashSet::new(),
-            game: SingleplayerGame::new(texture, Box::new(settings.input.clone())),
+            inputs: vec![],
+            started: Instant::now(),
+            game: SingleplayerGame::new(texture.clone(), Box::new(settings.input.clone()), settings.name.clone()),
+            mode: None,
+            texture,
             gilrs,
             gamepad,
             ui_occupation: (0.0, 0.0),
             since_last_tick: Duration::from_secs(0),
             settings,
+            openers: opener::load(),
+            menu: Menu::new(),
+            quit: false,
+            settings_file,
+            last_settings_check: Duration::from_secs(0),
+            sound,
+            theme: Theme::default(),
+            background: None,
+        };
+        let theme = model.settings.theme.clone();
+        model.set_theme(&theme);
+
+        model.game.set_config(model.settings.game);
+        model.game.set_seed(args.seed());
+        if let Some(path) = &args.replay {
+            match read_replay(path) {
+                Ok(replay) => {
+                    model.menu.close();
+                    model.game.play_replay(replay);
+                }
+                Err(e) => log::error!("Failed to read replay {path:?}: {e}"),
+            }
+        } else if let Some(mode) = args.mode {
+            model.menu.close();
+            model.menu_action(MenuAction::Play(mode));
+        }
+        model
+    }
+}
+
+/// Returns whether the schedule changed.
+fn garbage_schedule_ui(
+    ui: &mut egui::Ui,
+    schedule: &mut Option<GarbageSchedule>,
+    timeline_path: &mut String,
+    attacks: &[(u32, u32)],
+) -> bool {
+    let before = schedule.clone();
+    let name = match schedule {
+        None => "None",
+        Some(GarbageSchedule::Fixed { .. }) => "Fixed",
+        Some(GarbageSchedule::Random { .. }) => "Random bursts",
+        Some(GarbageSchedule::Timeline(_)) => "Attack timeline",
+    };
+    egui::ComboBox::from_label("Incoming garbage")
+        .selected_text(name)
+        .show_ui(ui, |ui| {
+            if ui.selectable_label(schedule.is_none(), "None").clicked() {
+                *schedule = None;
+            }
+            if ui.selectable_label(name == "Fixed", "Fixed").clicked() && name != "Fixed" {
+                *schedule = Some(GarbageSchedule::Fixed { lines: 4, interval: 5 * 60 });
+            }
+            if ui.selectable_label(name == "Random bursts", "Random bursts").clicked() && name != "Random bursts" {
+                *schedule = Some(GarbageSchedule::Random { min: 1, max: 6, interval: 4 * 60 });
+            }
+            if ui.selectable_label(name == "Attack timeline", "Attack timeline").clicked() && name != "Attack timeline" {
+                *schedule = Some(GarbageSchedule::Timeline(vec![]));
+            }
+        });
+
+    // intervals are shown in seconds rather than ticks
+    let seconds = |ui: &mut egui::Ui, interval: &mut u32| {
+        let mut seconds = *interval as f32 / 60.;
+        if ui.add(egui::DragValue::new(&mut seconds).speed(0.1).clamp_range(0.1..=60.).suffix(" s")).changed() {
+            *interval = (seconds * 60.) as u32;
+        }
+    };
+    match schedule {
+        Some(GarbageSchedule::Fixed { lines, interval }) => {
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(lines).clamp_range(1..=20).suffix(" lines"));
+                ui.label("every");
+                seconds(ui, interval);
+            });
+        }
+        Some(GarbageSchedule::Random { min, max, interval }) => {
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(min).clamp_range(1..=20));
+                ui.label("to");
+                ui.add(egui::DragValue::new(max).clamp_range(1..=20).suffix(" lines"));
+            });
+            ui.horizontal(|ui| {
+                ui.label("about every");
+                seconds(ui, interval);
+            });
+        }
+        Some(GarbageSchedule::Timeline(timeline)) => {
+            ui.label(format!("{} attacks", timeline.len()));
+            ui.text_edit_singleline(timeline_path);
+            ui.horizontal(|ui| {
+                if ui.button("Load").clicked() {
+                    match garbage::read_timeline(Path::new(timeline_path)) {
+                        Ok(loaded) => *timeline = loaded,
+                        Err(e) => log::error!("Failed to load attack timeline: {e}"),
+                    }
+                }
+                if ui.button("Save this game's attacks")
+                    .on_hover_text("Save the attacks you sent this game, to practice against later")
+                    .clicked() {
+                    if let Err(e) = garbage::write_timeline(Path::new(timeline_path), attacks) {
+                        log::error!("Failed to save attack timeline: {e}");
+                    }
+                }
+            });
         }
+        None => {}
     }
+
+    *schedule != before
+}
+
+/// Returns whether the theme should be saved as `name`.
+fn theme_ui(ui: &mut egui::Ui, theme: &mut Theme, name: &mut String) -> bool {
+    let mut save = false;
+    ui.collapsing("Edit theme", |ui| {
+        match &mut theme.background {
+            Background::Color(color) => {
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_srgb(color);
+                    ui.label("Background");
+                });
+            }
+            Background::Image(path) => {
+                ui.label(format!("Background image {}", path.display()));
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.color_edit_button_srgb(&mut theme.board);
+            ui.label("Board");
+        });
+        ui.add(egui::Slider::new(&mut theme.board_opacity, 0.0..=1.0).text("Board opacity"));
+        let mut grid = theme.grid.is_some();
+        ui.horizontal(|ui| {
+            if ui.checkbox(&mut grid, "Grid").changed() {
+                theme.grid = grid.then(|| [255, 255, 255, 30]);
+            }
+            if let Some(color) = &mut theme.grid {
+                ui.color_edit_button_srgba_unmultiplied(color);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.color_edit_button_srgb(&mut theme.warning);
+            ui.label("Garbage meter");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Ghost");
+            ui.selectable_value(&mut theme.ghost, GhostStyle::Skin, "Skin");
+            ui.selectable_value(&mut theme.ghost, GhostStyle::Outline, "Outline");
+            ui.selectable_value(&mut theme.ghost, GhostStyle::Hidden, "Hidden");
+        });
+        ui.add(egui::Slider::new(&mut theme.hud_scale, 0.5..=2.0).text("Text size"));
+        ui.checkbox(&mut theme.dark_mode, "Dark mode");
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(name);
+            save = ui.add_enabled(!name.is_empty(), egui::Button::new("Save theme"))
+                .on_hover_text("Save the theme to the themes folder, to pick it later or share it")
+                .clicked();
+        });
+    });
+    save
+}
+
+fn accessibility_ui(ui: &mut egui::Ui, accessibility: &mut Accessibility) {
+    ui.collapsing("Accessibility", |ui| {
+        egui::ComboBox::from_label("Palette")
+            .selected_text(match accessibility.palette {
+                Palette::Skin => "Skin colours",
+                Palette::Protanopia => "Protanopia",
+                Palette::Deuteranopia => "Deuteranopia",
+                Palette::Tritanopia => "Tritanopia",
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut accessibility.palette, Palette::Skin, "Skin colours");
+                ui.selectable_value(&mut accessibility.palette, Palette::Protanopia, "Protanopia");
+                ui.selectable_value(&mut accessibility.palette, Palette::Deuteranopia, "Deuteranopia");
+                ui.selectable_value(&mut accessibility.palette, Palette::Tritanopia, "Tritanopia");
+            });
+        ui.checkbox(&mut accessibility.glyphs, "Piece letters")
+            .on_hover_text("Draw the letter of its piece on every mino");
+        ui.checkbox(&mut accessibility.outlines, "Mino outlines")
+            .on_hover_text("Draw a dark outline around every mino");
+        ui.checkbox(&mut accessibility.visible_ghost, "Visible ghost")
+            .on_hover_text("Outline the ghost piece brightly");
+    });
+}
+
+/// Returns whether sounds should be loaded again.
+fn sound_ui(ui: &mut egui::Ui, settings: &mut SoundSettings) -> bool {
+    let mut reload = false;
+    ui.collapsing("Sound", |ui| {
+        ui.add(egui::Slider::new(&mut settings.volume, 0.0..=1.0).text("Volume"));
+        for sound in Sound::ALL {
+            let mut muted = settings.muted.contains(&sound);
+            if ui.checkbox(&mut muted, format!("Mute {}", sound.file_name().replace('_', " "))).changed() {
+                settings.muted.retain(|&s| s != sound);
+                if muted {
+                    settings.muted.push(sound);
+                }
+            }
+        }
+        if let Some(directory) = settings.directory() {
+            ui.label(format!("Sounds are read from {}", directory.display()))
+                .on_hover_text("Put a .wav or .ogg file named after the sound there, like lock.wav");
+        }
+        reload = ui.button("Reload sounds").clicked();
+    });
+    reload
+}
+
+/// Returns whether any of the spawn or top out rules were changed.
+fn spawn_ui(ui: &mut egui::Ui, spawn_row: &mut u32, spawn_fall: &mut bool, top_out: &mut TopOutRules) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Spawn row");
+        for row in 19..=21 {
+            changed |= ui.selectable_value(spawn_row, row, row.to_string()).changed();
+        }
+    });
+    changed |= ui.checkbox(spawn_fall, "Spawn and fall")
+        .on_hover_text("Pieces move down a row as soon as they spawn")
+        .changed();
+    ui.collapsing("Top out", |ui| {
+        changed |= ui.checkbox(&mut top_out.lock_out, "Lock out")
+            .on_hover_text("A piece locks entirely above the visible board")
+            .changed();
+        changed |= ui.checkbox(&mut top_out.partial_lock_out, "Partial lock out")
+            .on_hover_text("Part of a piece locks above the visible board")
+            .changed();
+        changed |= ui.checkbox(&mut top_out.block_out, "Block out")
+            .on_hover_text("A piece can't spawn because the stack is in the way")
+            .changed();
+        changed |= ui.checkbox(&mut top_out.garbage_out, "Garbage out")
+            .on_hover_text("Garbage pushes the stack past the top of the board")
+            .changed();
+    });
+    changed
+}
+
+/// Returns whether the lock delay rules were changed.
+fn lock_reset_ui(ui: &mut egui::Ui, lock_reset: &mut LockReset, max_lock_time: &mut Option<u32>) -> bool {
+    let name = |reset: LockReset| match reset {
+        LockReset::Move => "Move reset",
+        LockReset::Step => "Step reset",
+        LockReset::Infinite => "Infinite",
+        LockReset::None => "No reset",
+    };
+    let before = (*lock_reset, *max_lock_time);
+    egui::ComboBox::from_label("Lock delay")
+        .selected_text(name(*lock_reset))
+        .show_ui(ui, |ui| {
+            for option in [LockReset::Move, LockReset::Step, LockReset::Infinite, LockReset::None] {
+                ui.selectable_value(lock_reset, option, name(option));
+            }
+        });
+
+    ui.horizontal(|ui| {
+        let mut limited = max_lock_time.is_some();
+        ui.checkbox(&mut limited, "Max lock time")
+            .on_hover_text("Lock pieces that spent this many ticks on the stack, even if their lock delay was reset");
+        match (limited, max_lock_time.as_mut()) {
+            (true, Some(ticks)) => {
+                ui.add(egui::DragValue::new(ticks).clamp_range(1..=600).suffix(" ticks"));
+            }
+            (true, None) => *max_lock_time = Some(300),
+            (false, _) => *max_lock_time = None,
+        }
+    });
+    (*lock_reset, *max_lock_time) != before
+}
+
+/// Returns whether the rotation system was changed.
+fn rotation_system_ui(ui: &mut egui::Ui, system: &mut RotationSystem) -> bool {
+    let name = |system: RotationSystem| match system {
+        RotationSystem::Srs => "SRS",
+        RotationSystem::SrsPlus => "SRS+",
+        RotationSystem::SrsX => "SRS-X",
+        RotationSystem::Classic => "Classic",
+    };
+    let before = *system;
+    egui::ComboBox::from_label("Rotation system")
+        .selected_text(name(*system))
+        .show_ui(ui, |ui| {
+            for option in [RotationSystem::Srs, RotationSystem::SrsPlus, RotationSystem::SrsX, RotationSystem::Classic] {
+                ui.selectable_value(system, option, name(option));
+            }
+        });
+    *system != before
+}
+
+fn attack_table_ui(ui: &mut egui::Ui, attack: &mut AttackTable) -> bool {
+    let before = *attack;
+    let name = match attack {
+        AttackTable::Guideline => "Guideline",
+        AttackTable::Ppt => "Puyo Puyo Tetris",
+        AttackTable::TetrIo => "TETR.IO",
+        AttackTable::Custom(_) => "Custom",
+    };
+    let is_custom = matches!(attack, AttackTable::Custom(_));
+    egui::ComboBox::from_label("Attack table")
+        .selected_text(name)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(attack, AttackTable::Guideline, "Guideline");
+            ui.selectable_value(attack, AttackTable::Ppt, "Puyo Puyo Tetris");
+            ui.selectable_value(attack, AttackTable::TetrIo, "TETR.IO");
+            if ui.selectable_label(is_custom, "Custom").clicked() && !is_custom {
+                *attack = AttackTable::Custom(CustomAttack::default());
+            }
+        });
+
+    let AttackTable::Custom(table) = attack else { return *attack != before; };
+    egui::CollapsingHeader::new("Custom attack table").show(ui, |ui| {
+        egui::Grid::new("attack_grid").show(ui, |ui| {
+            for (name, value) in [
+                ("Single", &mut table.single),
+                ("Double", &mut table.double),
+                ("Triple", &mut table.triple),
+                ("Tetris", &mut table.tetris),
+                ("Mini T-spin single", &mut table.mini_tspin_single),
+                ("Mini T-spin double", &mut table.mini_tspin_double),
+                ("T-spin single", &mut table.tspin_single),
+                ("T-spin double", &mut table.tspin_double),
+                ("T-spin triple", &mut table.tspin_triple),
+                ("Back-to-back bonus", &mut table.back_to_back),
+                ("Perfect clear bonus", &mut table.perfect_clear),
+            ] {
+                ui.label(name);
+                ui.add(egui::DragValue::new(value).clamp_range(0..=20));
+                ui.end_row();
+            }
+        });
+        ui.label("Combo bonus");
+        ui.horizontal_wrapped(|ui| {
+            for value in &mut table.combo {
+                ui.add(egui::DragValue::new(value).clamp_range(0..=20));
+            }
+        });
+    });
+    *attack != before
+}
+
+/// A match between a player on the keyboard and one on the gamepad.
+fn new_versus(input: UserInput, texture: &wgpu::Texture, first_to: u32, config: GameConfig) -> VersusGame {
+    let players = [
+        Player::new("Keyboard".to_string(), Box::new(input), Device::Keyboard, texture.clone()),
+        Player::new("Gamepad".to_string(), Box::new(input), Device::Gamepad, texture.clone()),
+    ];
+    VersusGame::new(players, first_to, config, texture.clone(), thread_rng().gen())
 }
 
-fn get_config_file() -> Option<PathBuf> {
-    dirs::config_dir()
-        .map(|d| d.join(format!("{APP_NAME}.ron")))
-}
\ No newline at end of file
+/// A match between the player, on any device, and the bot.
+fn new_bot_versus(
+    input: UserInput,
+    name: &str,
+    bot: BotConfig,
+    texture: &wgpu::Texture,
+    first_to: u32,
+    config: GameConfig,
+) -> VersusGame {
+    let players = [
+        Player::new(name.to_string(), Box::new(input), Device::Any, texture.clone()),
+        Player::new("Cold Clear".to_string(), Box::new(BotInput::new(bot)), Device::None, texture.clone()),
+    ];
+    VersusGame::new(players, first_to, config, texture.clone(), thread_rng().gen())
+}
+
+fn get_state_file() -> Option<PathBuf> {
+    dirs::data_dir()
+        .map(|d| d.join(APP_NAME).join("state.ron"))
+}
+
+fn get_replay_file() -> Option<PathBuf> {
+    dirs::data_dir()
+        .map(|d| d.join(APP_NAME).join("replay.ron"))
+}
+
+fn get_timeline_file() -> Option<PathBuf> {
+    dirs::data_dir()
+        .map(|d| d.join(APP_NAME).join("attacks.ron"))
+}
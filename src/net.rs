@@ -0,0 +1,397 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use gilrs::Gamepad;
+use nannou::prelude::*;
+use rand::{Rng, thread_rng};
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+use crate::game::{Event, GameConfig};
+use crate::input::{InputEvent, InputSource, UserInput};
+use crate::ui::theme::Theme;
+use crate::versus::{Device, Player, TickInput, VersusGame};
+use crate::wgpu::Texture;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:23456";
+/// How many rounds have to be won to win an online match.
+const FIRST_TO: u32 = 3;
+/// How often, in ticks, both sides compare the state of their simulation.
+const HASH_INTERVAL: u64 = 60;
+
+/// The first message of a match, sent by the host.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+struct Hello {
+    seed: [u8; 16],
+    /// How many ticks after being pressed an input takes effect.
+    input_delay: u64,
+    config: GameConfig,
+}
+
+/// Everything sent between the two sides of a match, one JSON object per line.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum Message {
+    Hello(Hello),
+    Input { tick: u64, input: TickInput },
+    Hash { tick: u64, hash: u64 },
+}
+
+enum NetEvent {
+    Connected(TcpStream, Hello),
+    Message(Message),
+    Disconnected(String),
+}
+
+enum Connection {
+    Connecting,
+    Playing(Match),
+    Closed(String),
+}
+
+struct Match {
+    versus: VersusGame,
+    /// Lines to send to the other side, written to the connection on another thread.
+    writer: Sender<String>,
+    /// Which of the two players is on this side.
+    local: usize,
+    input_delay: u64,
+    /// The next tick to be simulated.
+    tick: u64,
+    /// The tick the next local input will take effect on.
+    next_local: u64,
+    inputs: [BTreeMap<u64, TickInput>; 2],
+    /// Presses made while waiting for the other side, sent with the next local input.
+    unsent: Vec<InputEvent>,
+    /// Hashes of the local simulation, kept until the other side's hash for the same tick arrives.
+    hashes: BTreeMap<u64, u64>,
+    remote_hashes: BTreeMap<u64, u64>,
+    desync: Option<u64>,
+}
+
+/// One side of an online 1v1 match.
+///
+/// Both sides simulate both games. Only inputs are exchanged: an input made on tick `t` takes
+/// effect on tick `t + input_delay` on both sides, and a tick is only simulated once the inputs
+/// of both players for it are known.
+pub struct NetGame {
+    connection: Connection,
+    events: Receiver<NetEvent>,
+    /// Whether this side is hosting the match, and thus player one.
+    host: bool,
+    input: UserInput,
+    texture: Texture,
+}
+
+impl NetGame {
+    /// Wait for someone to connect to `address`, and play against them.
+    pub fn host(
+        address: &str,
+        input_delay: u64,
+        config: GameConfig,
+        input: UserInput,
+        texture: Texture,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let address = address.to_string();
+        thread::spawn(move || {
+            let result = TcpListener::bind(&address)
+                .and_then(|listener| listener.accept())
+                .map(|(stream, _)| stream)
+                .and_then(|mut stream| {
+                    let hello = Hello { seed: thread_rng().gen(), input_delay, config };
+                    stream.write_all(to_line(&Message::Hello(hello))?.as_bytes())?;
+                    Ok((stream, hello))
+                });
+            match result {
+                Ok((stream, hello)) => run_connection(stream, Some(hello), tx),
+                Err(e) => {
+                    let _ = tx.send(NetEvent::Disconnected(e.to_string()));
+                }
+            }
+        });
+        Self::new(rx, true, input, texture)
+    }
+
+    /// Connect to a match hosted at `address`.
+    pub fn join(address: &str, input: UserInput, texture: Texture) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let address = address.to_string();
+        thread::spawn(move || match TcpStream::connect(&address) {
+            Ok(stream) => run_connection(stream, None, tx),
+            Err(e) => {
+                let _ = tx.send(NetEvent::Disconnected(e.to_string()));
+            }
+        });
+        Self::new(rx, false, input, texture)
+    }
+
+    fn new(events: Receiver<NetEvent>, host: bool, input: UserInput, texture: Texture) -> Self {
+        Self {
+            connection: Connection::Connecting,
+            events,
+            host,
+            input,
+            texture,
+        }
+    }
+
+    fn start(&self, stream: TcpStream, hello: Hello) -> Match {
+        let local = if self.host { 0 } else { 1 };
+        let player = |idx: usize| {
+            let (name, input, device): (_, Box<dyn InputSource>, _) = if idx == local {
+                ("You", Box::new(self.input), Device::Any)
+            } else {
+                ("Opponent", Box::new(UserInput::default()), Device::None)
+            };
+            Player::new(name.to_string(), input, device, self.texture.clone())
+        };
+
+        // nothing is pressed during the first ticks, before the first inputs take effect
+        let mut inputs = [BTreeMap::new(), BTreeMap::new()];
+        for tick in 0..hello.input_delay {
+            for inputs in &mut inputs {
+                inputs.insert(tick, TickInput::default());
+            }
+        }
+
+        let mut versus = VersusGame::new(
+            [player(0), player(1)],
+            FIRST_TO,
+            hello.config,
+            self.texture.clone(),
+            hello.seed,
+        );
+        versus.listen_to(local);
+        Match {
+            versus,
+            writer: spawn_writer(stream),
+            local,
+            input_delay: hello.input_delay,
+            tick: 0,
+            next_local: hello.input_delay,
+            inputs,
+            unsent: vec![],
+            hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+            desync: None,
+        }
+    }
+
+    fn receive(&mut self) {
+        loop {
+            let event = match self.events.try_recv() {
+                Ok(event) => event,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => NetEvent::Disconnected("Connection lost".to_string()),
+            };
+
+            match event {
+                NetEvent::Connected(stream, hello) => {
+                    log::info!("Connected, starting match with {hello:?}");
+                    self.connection = Connection::Playing(self.start(stream, hello));
+                }
+                NetEvent::Message(message) => {
+                    if let Connection::Playing(game) = &mut self.connection {
+                        game.receive(message);
+                    }
+                }
+                NetEvent::Disconnected(reason) => {
+                    log::info!("Online match ended: {reason}");
+                    self.connection = Connection::Closed(reason);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Match {
+    fn remote(&self) -> usize {
+        1 - self.local
+    }
+
+    fn send(&self, message: &Message) -> Result<(), String> {
+        let line = to_line(message).map_err(|e| e.to_string())?;
+        // the writer only stops once writing to the connection failed
+        self.writer.send(line).map_err(|_| "Connection lost".to_string())
+    }
+
+    fn receive(&mut self, message: Message) {
+        match message {
+            Message::Input { tick, input } => {
+                let remote = self.remote();
+                self.inputs[remote].insert(tick, input);
+            }
+            Message::Hash { tick, hash } => {
+                self.remote_hashes.insert(tick, hash);
+            }
+            Message::Hello(_) => log::warn!("Received a second hello, ignoring it"),
+        }
+    }
+
+    fn update(
+        &mut self,
+        keys: &HashSet<Key>,
+        gamepad: Option<Gamepad<'_>>,
+        inputs: &[InputEvent],
+    ) -> Result<(), String> {
+        // don't run ahead of the simulation by more than the input delay while waiting for the
+        // other side
+        self.unsent.extend_from_slice(inputs);
+        if self.next_local <= self.tick + self.input_delay {
+            let unsent = std::mem::take(&mut self.unsent);
+            let [first, second] = self.versus.tick_inputs(keys, gamepad, &unsent);
+            let input = if self.local == 0 { first } else { second };
+            self.send(&Message::Input { tick: self.next_local, input: input.clone() })?;
+            self.inputs[self.local].insert(self.next_local, input);
+            self.next_local += 1;
+        }
+
+        while self.inputs.iter().all(|inputs| inputs.contains_key(&self.tick)) {
+            let tick = self.tick;
+            let inputs = [0, 1].map(|idx| self.inputs[idx].remove(&tick).unwrap());
+            self.versus.tick(inputs);
+            self.tick += 1;
+
+            if tick % HASH_INTERVAL == 0 {
+                let hash = self.versus.board_hash();
+                self.hashes.insert(tick, hash);
+                self.send(&Message::Hash { tick, hash })?;
+            }
+        }
+
+        let checked: Vec<u64> = self.remote_hashes.keys()
+            .copied()
+            .filter(|tick| self.hashes.contains_key(tick))
+            .collect();
+        for tick in checked {
+            let remote = self.remote_hashes.remove(&tick);
+            let local = self.hashes.remove(&tick);
+            if self.desync.is_none() && remote != local {
+                log::error!("Desync detected on tick {tick}");
+                self.desync = Some(tick);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl crate::State for NetGame {
+    fn update(&mut self, keys: &HashSet<Key>, gamepad: Option<Gamepad<'_>>, inputs: &[InputEvent]) {
+        self.receive();
+        if let Connection::Playing(game) = &mut self.connection {
+            if let Err(e) = game.update(keys, gamepad, inputs) {
+                log::error!("Failed to send to opponent: {e}");
+                self.connection = Connection::Closed(e);
+            }
+        }
+    }
+
+    // there's no restart, as a rematch has to start on the same tick on both sides, which it does
+    // through the reset in the inputs sent to the other side
+
+    fn results(&self) -> Option<Vec<String>> {
+        match &self.connection {
+            Connection::Playing(game) => game.versus.results(),
+            _ => None,
+        }
+    }
+
+    fn take_events(&mut self) -> Vec<Event> {
+        match &mut self.connection {
+            Connection::Playing(game) => game.versus.take_events(),
+            _ => vec![],
+        }
+    }
+
+    fn render(&self, draw: &Draw, rect: Rect, theme: &Theme) {
+        let message = match &self.connection {
+            Connection::Connecting if self.host => Some("Waiting for an opponent to connect".to_string()),
+            Connection::Connecting => Some("Connecting".to_string()),
+            Connection::Playing(game) => {
+                game.versus.render(draw, rect, theme);
+                game.desync.map(|tick| format!("Desync detected on tick {tick}"))
+            }
+            Connection::Closed(reason) => Some(format!("Disconnected: {reason}")),
+        };
+        if let Some(message) = message {
+            theme.text(draw, &message)
+                .font_size(theme.font_size(rect.w() / 50.))
+                .w(rect.w())
+                .x_y(rect.x(), rect.bottom() + rect.h() / 4.)
+                .center_justify();
+        }
+    }
+}
+
+fn to_line(message: &Message) -> serde_json::Result<String> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    Ok(line)
+}
+
+/// Write every line sent through the returned channel to `stream` on another thread, so the game
+/// doesn't wait on the connection. The channel closes once writing fails.
+fn spawn_writer(mut stream: TcpStream) -> Sender<String> {
+    let (tx, rx) = mpsc::channel::<String>();
+    thread::spawn(move || {
+        for line in rx {
+            if let Err(e) = stream.write_all(line.as_bytes()) {
+                log::error!("Failed to send to opponent: {e}");
+                return;
+            }
+        }
+    });
+    tx
+}
+
+/// Read messages from `stream` until it closes, starting with the hello if it hasn't been
+/// exchanged yet.
+fn run_connection(stream: TcpStream, hello: Option<Hello>, tx: Sender<NetEvent>) {
+    let _ = stream.set_nodelay(true);
+    let reader = match stream.try_clone() {
+        Ok(reader) => reader,
+        Err(e) => {
+            let _ = tx.send(NetEvent::Disconnected(e.to_string()));
+            return;
+        }
+    };
+
+    let mut hello = hello;
+    let mut stream = Some(stream);
+    if let Some(hello) = hello {
+        let _ = tx.send(NetEvent::Connected(stream.take().unwrap(), hello));
+    }
+
+    for line in BufReader::new(reader).lines() {
+        let message = match line.map(|line| serde_json::from_str::<Message>(&line)) {
+            Ok(Ok(message)) => message,
+            Ok(Err(e)) => {
+                let _ = tx.send(NetEvent::Disconnected(format!("Invalid message: {e}")));
+                return;
+            }
+            Err(e) => {
+                let _ = tx.send(NetEvent::Disconnected(e.to_string()));
+                return;
+            }
+        };
+
+        let event = match message {
+            Message::Hello(received) if hello.is_none() => {
+                let Some(stream) = stream.take() else { continue; };
+                hello = Some(received);
+                NetEvent::Connected(stream, received)
+            }
+            message => NetEvent::Message(message),
+        };
+        if tx.send(event).is_err() {
+            // the game was closed
+            return;
+        }
+    }
+    let _ = tx.send(NetEvent::Disconnected("Opponent left".to_string()));
+}
@@ -2,121 +2,236 @@ use std::collections::HashSet;
 
 use gilrs::Gamepad;
 use nannou::prelude::*;
-use rand::{Rng, SeedableRng, thread_rng};
-use rand_pcg::Pcg64Mcg;
+use practris_engine::singleplayer::{Jump, SaveState, Singleplayer};
 
-use crate::game::{Game, GameConfig, PlayerUpdate};
+use crate::analysis::GameRecord;
+use crate::fumen::Position;
+use crate::game::{Event, GameConfig};
+use crate::garbage::GarbageSchedule;
 use crate::ui::SingleplayerGameUi;
-use crate::input::InputSource;
+use crate::ui::theme::Theme;
+use crate::input::{InputEvent, InputSource};
+use crate::marathon::MarathonCurve;
+use crate::opener::{Opener, OpenerStatus};
+use crate::replay::Replay;
+use crate::rotation::Kick;
+use crate::spectate::{SpectatorMessage, SpectatorServer};
+use crate::ui::Outline;
 use crate::wgpu::Texture;
 
-const RESET_TIME: usize = 40;
-
+/// A [`Singleplayer`] game, drawn in the window and streamed to spectators.
 pub struct SingleplayerGame {
+    core: Singleplayer,
     ui: SingleplayerGameUi,
     texture: Texture,
-    game: Game,
-    pub(crate) input: Box<dyn InputSource>,
-    pub state: State,
-    piece_rng: Pcg64Mcg,
-    garbage_rng: Pcg64Mcg,
-    reset_countdown: f32,
-}
-
-#[derive(Debug)]
-pub enum State {
-    Playing,
-    GameOver(u32),
-    Starting(u32),
+    /// The player's name, shown above the board and to spectators.
+    name: String,
+    spectators: Option<SpectatorServer>,
 }
 
 impl SingleplayerGame {
-    pub fn new(texture: Texture, input: Box<dyn InputSource>) -> Self {
-        let mut thread_rng = thread_rng();
-        let mut rng = Pcg64Mcg::from_seed(thread_rng.gen());
-        let mut garbage_rng = Pcg64Mcg::from_seed(thread_rng.gen());
-        let game = Game::new(GameConfig::fast_config(), &mut rng);
-
+    pub fn new(texture: Texture, input: Box<dyn InputSource>, name: String) -> Self {
+        let core = Singleplayer::new(input);
         Self {
-            ui: SingleplayerGameUi::new(&game, "amogus".to_string(), texture.clone()),
+            ui: SingleplayerGameUi::new(core.game(), name.clone(), texture.clone()),
+            core,
             texture,
-            game,
-            input,
-            state: State::Starting(300),
-            piece_rng: rng,
-            garbage_rng,
-            reset_countdown: 1.,
+            name,
+            spectators: None,
         }
     }
-}
 
-impl crate::State for SingleplayerGame {
-    fn update(&mut self, keys: &HashSet<Key>, gamepad: Option<Gamepad<'_>>) {
-        let do_update = match self.state {
-            State::GameOver(0) => {
-                false
-            }
-            State::GameOver(ref mut delay) => {
-                *delay -= 1;
-                true
-            }
-            State::Starting(0) => {
-                self.state = State::Playing;
-                true
-            }
-            State::Starting(ref mut delay) => {
-                *delay -= 1;
-                false
+    /// Every placement made to get to the current point in the game, for analysis.
+    pub fn record(&self) -> GameRecord {
+        self.core.record()
+    }
+
+    /// Bring the board up to date after the game jumped somewhere its events don't describe.
+    fn apply_jump(&mut self) {
+        let Some(jump) = self.core.take_jump() else { return; };
+        match jump {
+            Jump::Restarted => {
+                self.ui = SingleplayerGameUi::new(self.core.game(), self.name.clone(), self.texture.clone());
             }
-            State::Playing => true,
+            Jump::Restored => self.ui.sync(self.core.game()),
+        }
+        self.ui.set_time(self.core.time());
+        self.update_hud();
+        self.update_outlines();
+        self.send_snapshot();
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.ui.set_name(name.clone());
+        self.name = name;
+    }
+
+    /// Change the rules of the game, taking effect immediately.
+    pub fn set_config(&mut self, config: GameConfig) {
+        self.core.set_config(config);
+    }
+
+    /// Play marathon along `curve`, or stop playing marathon if `None`.
+    pub fn set_marathon(&mut self, curve: Option<MarathonCurve>) {
+        self.core.set_marathon(curve);
+        self.apply_jump();
+    }
+
+    fn update_hud(&mut self) {
+        let game = self.core.game();
+        let mut hud = match self.core.marathon() {
+            Some(marathon) => vec![
+                format!("Level {}", marathon.level()),
+                format!("{} lines to next", marathon.lines_to_next_level()),
+            ],
+            None => vec![],
         };
+        if let Some(reason) = game.game_over_reason() {
+            hud.push(reason.to_string());
+        }
+        self.ui.set_hud(hud);
+    }
 
-        if do_update {
-            let controller = self.input.controller(keys, gamepad);
-            let events = self.game.update(controller, &mut self.piece_rng, &mut self.garbage_rng);
-            let update = PlayerUpdate {
-                events,
-                garbage_queue: 0
-            };
+    /// Send garbage to the player following `schedule`, or stop sending garbage if `None`.
+    pub fn set_garbage_schedule(&mut self, schedule: Option<GarbageSchedule>) {
+        self.core.set_garbage_schedule(schedule);
+    }
+
+    /// Start every game with `seed`, or a random seed if `None`.
+    pub fn set_seed(&mut self, seed: Option<[u8; 16]>) {
+        self.core.set_seed(seed);
+    }
+
+    /// Start every game from `position`, or from an empty board if `None`.
+    pub fn set_start_position(&mut self, position: Option<Position>) {
+        self.core.set_start_position(position);
+        self.apply_jump();
+    }
+
+    /// The board as it is now, with the hold and queue, as a fumen.
+    pub fn board_fumen(&self) -> String {
+        self.core.board_fumen()
+    }
+
+    /// The current game, if it can be played back.
+    pub fn replay(&self) -> Option<&Replay> {
+        self.core.replay()
+    }
+
+    /// Play with `input`, or with `input` once the replay being played back is over.
+    pub fn set_input(&mut self, input: Box<dyn InputSource>) {
+        self.core.set_input(input);
+    }
+
+    /// Watch `replay` being played back, until it ends or the game is restarted.
+    pub fn play_replay(&mut self, replay: Replay) {
+        self.core.play_replay(replay);
+        self.apply_jump();
+    }
 
-            if let State::Playing = self.state {
-                for event in &update.events {
-                    use crate::game::Event::*;
-                    match event {
-                        GameOver => {
-                            self.state = State::GameOver(300);
-                        }
-                        _ => {}
-                    }
+    pub fn last_kick(&self) -> Option<Kick> {
+        self.core.last_kick()
+    }
+
+    pub fn attacks(&self) -> &[(u32, u32)] {
+        self.core.attacks()
+    }
+
+    /// Stream the game to spectators through `server`, or stop streaming it if `None`.
+    pub fn set_spectators(&mut self, server: Option<SpectatorServer>) {
+        self.spectators = server;
+    }
+
+    /// Let spectators know the game jumped to a different state.
+    fn send_snapshot(&mut self) {
+        if let Some(server) = &mut self.spectators {
+            server.send_snapshot(&self.name, self.core.game());
+        }
+    }
+
+    /// Practice the given opener, or stop practicing one if `None`.
+    pub fn set_opener(&mut self, opener: Option<Opener>) {
+        self.core.set_opener(opener);
+        self.apply_jump();
+    }
+
+    pub fn set_coaching(&mut self, coaching: bool) {
+        self.core.set_coaching(coaching);
+        self.update_outlines();
+    }
+
+    fn update_outlines(&mut self) {
+        let game = self.core.game();
+        let mut outlines: Vec<Outline> = match self.core.opener() {
+            Some(trainer) => match trainer.status() {
+                OpenerStatus::Building => {
+                    let current = game.falling_piece().map(|piece| piece.kind.0);
+                    trainer.next_target(current, game.board.hold_piece)
+                        .map(|target| Outline {
+                            cells: target.cells,
+                            color: Rgba::new(1., 1., 1., 0.8),
+                            label: None,
+                        })
+                        .into_iter()
+                        .collect()
                 }
-            }
+                OpenerStatus::Deviated(piece) => vec![Outline {
+                    cells: piece.cells(),
+                    color: Rgba::new(1., 0.2, 0.2, 1.),
+                    label: Some("Deviation".to_string()),
+                }],
+                OpenerStatus::Completed => vec![],
+            },
+            None => vec![],
+        };
 
-            self.ui.update(update);
+        for (rank, hint) in self.core.hints().iter().enumerate() {
+            let score = hint.score.map_or(String::new(), |score| score.to_string());
+            let label = if hint.hold {
+                format!("hold: {score}")
+            } else {
+                score
+            };
+            outlines.push(Outline {
+                cells: hint.location.cells(),
+                color: Rgba::new(0.3, 1., 0.3, 1. - rank as f32 * 0.3),
+                label: Some(label),
+            });
         }
 
-        let actions = self.input.actions(keys, gamepad);
-        if actions.reset {
-            match self.reset_countdown {
-                x if x <= 0. => {
-                    self.reset_countdown = 1.;
-
-                    let seed = thread_rng().gen();
-                    let mut rng = Pcg64Mcg::from_seed(seed);
-                    let game = Game::new(GameConfig::fast_config(), &mut rng);
-                    self.ui = SingleplayerGameUi::new(&game, "amogus".to_string(), self.texture.clone());
-                    self.game = game;
-                    self.state = State::Playing;
-                    self.piece_rng = rng;
-                },
-                _ => self.reset_countdown -= 1. / RESET_TIME as f32,
+        self.ui.set_outlines(outlines);
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        self.core.save_state()
+    }
+
+    pub fn load_state(&mut self, save: SaveState) {
+        self.core.load_state(save);
+        self.apply_jump();
+    }
+}
+
+impl crate::State for SingleplayerGame {
+    fn update(&mut self, keys: &HashSet<Key>, gamepad: Option<Gamepad<'_>>, inputs: &[InputEvent]) {
+        if let Some(server) = &mut self.spectators {
+            server.accept(&self.name, self.core.game());
+        }
+
+        if let Some(update) = self.core.update(keys, gamepad, inputs) {
+            if let Some(server) = &mut self.spectators {
+                server.send(&SpectatorMessage::Update(update.clone()));
             }
-        } else {
-            self.reset_countdown = 1.;
+            self.ui.update(update);
         }
+        self.apply_jump();
+        // hints arrive whenever the bot is done thinking, and the opener moves on with every piece
+        self.update_hud();
+        self.update_outlines();
     }
 
-    fn render(&self, draw: &Draw, rect: Rect) {
-        self.ui.draw(draw, rect);
+    fn render(&self, draw: &Draw, rect: Rect, theme: &Theme) {
+        self.ui.draw(draw, rect, theme);
 
         // To make the reset button feel slightly more 'tactile' - as in, not just a looping value,
         // we introduce a percentage (20%) of the time the reset bind is pressed, that no visual
@@ -124,11 +239,25 @@ impl crate::State for SingleplayerGame {
         // This also means that after a reset happens, the bar doesn't immediately start filling in
         // again, which also improves the feel of this function
         const RESET_IDLE: f32 = 0.8;
-        if self.reset_countdown < RESET_IDLE {
-            let reset_rect = Rect::from_wh(Vec2::new(rect.w() * (1. - self.reset_countdown / RESET_IDLE), 10.));
+        let reset_countdown = self.core.reset_countdown();
+        if reset_countdown < RESET_IDLE {
+            let reset_rect = Rect::from_wh(Vec2::new(rect.w() * (1. - reset_countdown / RESET_IDLE), 10.));
             let reset_rect = reset_rect.bottom_left_of(rect);
             draw.a::<nannou::draw::primitive::Rect>(reset_rect.into())
-                .color(RED);
+                .color(theme.warning_color());
         }
     }
-}
\ No newline at end of file
+
+    fn restart(&mut self) {
+        self.core.restart();
+        self.apply_jump();
+    }
+
+    fn results(&self) -> Option<Vec<String>> {
+        self.core.results()
+    }
+
+    fn take_events(&mut self) -> Vec<Event> {
+        self.core.take_events()
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use gilrs::Gamepad;
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Game, PlayerUpdate};
+use crate::input::InputEvent;
+use crate::ui::SingleplayerGameUi;
+use crate::ui::theme::Theme;
+use crate::wgpu::Texture;
+
+pub const DEFAULT_PORT: u16 = 23457;
+
+/// What is streamed to spectators, one JSON object per line.
+///
+/// Spectators first receive a snapshot of the whole game, followed by the update of every tick.
+/// Another snapshot is sent whenever the game jumps, for example when it is restarted or a
+/// placement is undone.
+#[derive(Serialize, Deserialize)]
+pub enum SpectatorMessage {
+    Snapshot { name: String, game: Game },
+    Update(PlayerUpdate),
+}
+
+/// Streams a game to everyone who connects to it.
+pub struct SpectatorServer {
+    listener: TcpListener,
+    /// One channel per spectator, each sending to a thread writing to its connection.
+    spectators: Vec<Sender<String>>,
+}
+
+impl SpectatorServer {
+    pub fn bind(port: u16) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        log::info!("Accepting spectators on port {port}");
+        Ok(Self {
+            listener,
+            spectators: vec![],
+        })
+    }
+
+    /// Let in everyone who connected since the last call, sending them the game so far.
+    pub fn accept(&mut self, name: &str, game: &Game) {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, address)) => {
+                    log::info!("Spectator connected from {address}");
+                    stream
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    log::error!("Failed to accept spectator: {e}");
+                    return;
+                }
+            };
+
+            let (tx, rx) = mpsc::channel::<String>();
+            thread::spawn(move || {
+                let mut stream = stream;
+                let _ = stream.set_nonblocking(false);
+                for line in rx {
+                    if stream.write_all(line.as_bytes()).is_err() {
+                        return;
+                    }
+                }
+            });
+            if let Some(line) = to_line(&snapshot(name, game)) {
+                let _ = tx.send(line);
+            }
+            self.spectators.push(tx);
+        }
+    }
+
+    pub fn send(&mut self, message: &SpectatorMessage) {
+        let Some(line) = to_line(message) else { return; };
+        // spectators whose connection closed have dropped their receiver
+        self.spectators.retain(|spectator| spectator.send(line.clone()).is_ok());
+    }
+
+    pub fn send_snapshot(&mut self, name: &str, game: &Game) {
+        self.send(&snapshot(name, game));
+    }
+}
+
+fn snapshot(name: &str, game: &Game) -> SpectatorMessage {
+    SpectatorMessage::Snapshot {
+        name: name.to_string(),
+        game: game.clone(),
+    }
+}
+
+fn to_line(message: &SpectatorMessage) -> Option<String> {
+    match serde_json::to_string(message) {
+        Ok(mut line) => {
+            line.push('\n');
+            Some(line)
+        }
+        Err(e) => {
+            log::error!("Failed to serialize spectator message: {e}");
+            None
+        }
+    }
+}
+
+enum Connection {
+    Connecting,
+    Watching(SingleplayerGameUi),
+    Closed(String),
+}
+
+/// Watches a game streamed by a [`SpectatorServer`].
+pub struct SpectatorGame {
+    connection: Connection,
+    messages: Receiver<Result<SpectatorMessage, String>>,
+    texture: Texture,
+}
+
+impl SpectatorGame {
+    pub fn connect(address: &str, texture: Texture) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let address = address.to_string();
+        thread::spawn(move || {
+            let stream = match TcpStream::connect(&address) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    let _ = tx.send(Err(e.to_string()));
+                    return;
+                }
+            };
+            for line in BufReader::new(stream).lines() {
+                let message = line.map_err(|e| e.to_string())
+                    .and_then(|line| serde_json::from_str(&line).map_err(|e| e.to_string()));
+                let failed = message.is_err();
+                if tx.send(message).is_err() || failed {
+                    return;
+                }
+            }
+            let _ = tx.send(Err("The game was closed".to_string()));
+        });
+
+        Self {
+            connection: Connection::Connecting,
+            messages: rx,
+            texture,
+        }
+    }
+}
+
+impl crate::State for SpectatorGame {
+    fn update(&mut self, _: &HashSet<Key>, _: Option<Gamepad<'_>>, _: &[InputEvent]) {
+        loop {
+            let message = match self.messages.try_recv() {
+                Ok(message) => message,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => Err("Connection lost".to_string()),
+            };
+
+            match message {
+                Ok(SpectatorMessage::Snapshot { name, game }) => {
+                    let mut ui = SingleplayerGameUi::new(&game, name, self.texture.clone());
+                    ui.sync(&game);
+                    self.connection = Connection::Watching(ui);
+                }
+                Ok(SpectatorMessage::Update(update)) => {
+                    if let Connection::Watching(ui) = &mut self.connection {
+                        ui.update(update);
+                    }
+                }
+                Err(e) => {
+                    log::info!("Stopped spectating: {e}");
+                    self.connection = Connection::Closed(e);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn render(&self, draw: &Draw, rect: Rect, theme: &Theme) {
+        let message = match &self.connection {
+            Connection::Connecting => "Connecting".to_string(),
+            Connection::Watching(ui) => {
+                ui.draw(draw, rect, theme);
+                return;
+            }
+            Connection::Closed(reason) => format!("Disconnected: {reason}"),
+        };
+        theme.text(draw, &message)
+            .font_size(theme.font_size(rect.w() / 50.))
+            .w(rect.w())
+            .xy(rect.xy())
+            .center_justify();
+    }
+}
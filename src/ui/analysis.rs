@@ -0,0 +1,136 @@
+use libtetris::{Board, CellColor, ColoredRow, FallingPiece, Row};
+use nannou_egui::egui::{self, Color32, Pos2, Rect, Sense, Stroke, Vec2};
+
+use crate::analysis::{AnalysisJob, PlacementAnalysis};
+
+const CELL_SIZE: f32 = 12.;
+const VIS_BOARD: i32 = 20;
+
+/// Shows how every placement of a game compares to the bot's, to step through after a game.
+pub struct AnalysisWindow {
+    /// The analysis while it's still running.
+    job: Option<AnalysisJob>,
+    results: Vec<PlacementAnalysis>,
+    selected: Option<usize>,
+}
+
+impl AnalysisWindow {
+    pub fn new(job: AnalysisJob) -> Self {
+        Self {
+            job: Some(job),
+            results: vec![],
+            selected: None,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::CtxRef, open: &mut bool) {
+        if let Some(results) = self.job.as_ref().and_then(AnalysisJob::poll) {
+            // start at the first blunder, as that's probably what the player is looking for
+            self.selected = results.iter().position(PlacementAnalysis::is_blunder)
+                .or(if results.is_empty() { None } else { Some(0) });
+            self.results = results;
+            self.job = None;
+        }
+
+        egui::Window::new("Analysis")
+            .open(open)
+            .show(ctx, |ui| {
+                if self.job.is_some() {
+                    ui.label("Analysing placements...");
+                    return;
+                }
+                if self.results.is_empty() {
+                    ui.label("No placements to analyse");
+                    return;
+                }
+
+                let blunders = self.results.iter().filter(|a| a.is_blunder()).count();
+                ui.label(format!("{} placements, {} blunders", self.results.len(), blunders));
+
+                egui::ScrollArea::horizontal().show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        for (idx, analysis) in self.results.iter().enumerate() {
+                            let fill = match analysis.loss() {
+                                None => Color32::from_gray(90),
+                                Some(_) if analysis.is_blunder() => Color32::from_rgb(180, 40, 40),
+                                Some(loss) if loss > 0 => Color32::from_rgb(150, 130, 40),
+                                Some(_) => Color32::from_rgb(40, 130, 40),
+                            };
+                            let hover = match analysis.loss() {
+                                Some(loss) => format!("-{}", loss),
+                                None => "Not analysed".to_string(),
+                            };
+                            let button = egui::Button::new((idx + 1).to_string()).fill(fill);
+                            if ui.add(button).on_hover_text(hover).clicked() {
+                                self.selected = Some(idx);
+                            }
+                        }
+                    });
+                });
+
+                let Some(selected) = self.selected else { return; };
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(selected > 0, egui::Button::new("◀")).clicked() {
+                        self.selected = Some(selected - 1);
+                    }
+                    ui.label(format!("Placement {}", selected + 1));
+                    if ui.add_enabled(selected + 1 < self.results.len(), egui::Button::new("▶")).clicked() {
+                        self.selected = Some(selected + 1);
+                    }
+                });
+
+                let analysis = &self.results[selected];
+                ui.horizontal(|ui| {
+                    ui.vertical(|ui| {
+                        match analysis.played_score {
+                            Some(score) => ui.label(format!("Played: {}", score)),
+                            None => ui.label("Played: the bot can't make this placement"),
+                        };
+                        draw_board(ui, &analysis.board, &analysis.played, Color32::WHITE);
+                    });
+                    ui.vertical(|ui| {
+                        ui.label(format!("Bot: {}", analysis.best_score));
+                        draw_board(ui, &analysis.board, &analysis.best, Color32::GREEN);
+                    });
+                });
+            });
+    }
+}
+
+fn draw_board(ui: &mut egui::Ui, board: &Board<ColoredRow>, piece: &FallingPiece, outline: Color32) {
+    let size = Vec2::new(10. * CELL_SIZE, VIS_BOARD as f32 * CELL_SIZE);
+    let (response, painter) = ui.allocate_painter(size, Sense::hover());
+    let origin = response.rect.left_bottom();
+    let cell_rect = |x: i32, y: i32| Rect::from_min_size(
+        Pos2::new(origin.x + x as f32 * CELL_SIZE, origin.y - (y + 1) as f32 * CELL_SIZE),
+        Vec2::splat(CELL_SIZE),
+    );
+
+    painter.rect_filled(response.rect, 0., Color32::from_gray(20));
+    for y in 0..VIS_BOARD {
+        let row = board.get_row(y);
+        for x in 0..10 {
+            if let Some(color) = cell_color(row.cell_color(x as usize)) {
+                painter.rect_filled(cell_rect(x, y), 0., color);
+            }
+        }
+    }
+    for (x, y) in piece.cells() {
+        painter.rect_stroke(cell_rect(x, y), 0., Stroke::new(2., outline));
+    }
+}
+
+fn cell_color(color: CellColor) -> Option<Color32> {
+    Some(match color {
+        CellColor::I => Color32::from_rgb(50, 180, 220),
+        CellColor::O => Color32::from_rgb(220, 200, 50),
+        CellColor::T => Color32::from_rgb(170, 60, 190),
+        CellColor::L => Color32::from_rgb(230, 130, 40),
+        CellColor::J => Color32::from_rgb(50, 80, 200),
+        CellColor::S => Color32::from_rgb(90, 190, 60),
+        CellColor::Z => Color32::from_rgb(210, 50, 50),
+        CellColor::Garbage | CellColor::Unclearable => Color32::from_gray(120),
+        CellColor::Empty => return None,
+    })
+}
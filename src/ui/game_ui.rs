@@ -7,12 +7,23 @@ use nannou::prelude::*;
 
 use crate::game::{*, Event};
 use crate::ui::skin::Skin;
+use crate::ui::theme::{GhostStyle, Theme};
 use crate::util::RectExt;
 use crate::wgpu::Texture;
 
 pub struct SingleplayerGameUi {
     draw_state: GameDrawState,
     time: u32,
+    outlines: Vec<Outline>,
+    /// Lines of text shown under the hold piece, such as the level in marathon.
+    hud: Vec<String>,
+}
+
+/// A piece drawn as an outline on top of the board, such as the target placement of an opener.
+pub struct Outline {
+    pub cells: [(i32, i32); 4],
+    pub color: Rgba,
+    pub label: Option<String>,
 }
 
 pub struct GameDrawState {
@@ -50,6 +61,8 @@ impl SingleplayerGameUi {
                 texture,
             ),
             time: 0,
+            outlines: vec![],
+            hud: vec![],
         }
     }
 
@@ -61,9 +74,53 @@ impl SingleplayerGameUi {
         self.draw_state.update(update, self.time);
     }
 
-    pub fn draw(&self, draw: &Draw, rect: Rect) {
+    pub fn time(&self) -> u32 {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: u32) {
+        self.time = time;
+    }
+
+    /// Discard any state built up from events and rebuild it from `game` directly.
+    ///
+    /// Used whenever the game jumps to a state that could not be reached through events, such as
+    /// when a placement is undone.
+    pub fn sync(&mut self, game: &Game) {
+        self.draw_state.sync(game);
+    }
+
+    pub fn set_outlines(&mut self, outlines: Vec<Outline>) {
+        self.outlines = outlines;
+    }
+
+    pub fn set_hud(&mut self, hud: Vec<String>) {
+        self.hud = hud;
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.draw_state.name = name;
+    }
+
+    pub fn draw(&self, draw: &Draw, rect: Rect, theme: &Theme) {
         let sq = letterbox(rect);
-        self.draw_state.draw(draw, sq);
+        self.draw_state.draw(draw, sq, theme);
+        for outline in &self.outlines {
+            self.draw_state.draw_outline(draw, sq, outline);
+        }
+
+        let (play_area, mino_size) = play_area(sq);
+        for (idx, line) in self.hud.iter().enumerate() {
+            let rect = Rect::from_w_h(mino_size * 5., mino_size)
+                .left_of(play_area)
+                .align_bottom_of(play_area)
+                .shift_y((self.hud.len() - idx) as f32 * mino_size * 1.5);
+            theme.text(draw, line)
+                .wh(rect.wh())
+                .xy(rect.xy())
+                .font_size(theme.font_size(mino_size * 0.7))
+                .right_justify();
+        }
     }
 }
 
@@ -177,20 +234,87 @@ impl GameDrawState {
                         self.board.insert(0, row);
                     }
                 }
-                Event::GameOver => self.dead = true,
+                Event::GameOver(_) => self.dead = true,
                 _ => {}
             }
         }
     }
 
-    pub fn draw(&self, draw: &Draw, rect: Rect) {
-        const VIS_BOARD: usize = 20;
+    pub fn sync(&mut self, game: &Game) {
+        for (y, row) in self.board.iter_mut().enumerate() {
+            *row = *game.board.get_row(y as i32);
+        }
+        self.state = match game.falling_piece() {
+            Some(piece) => {
+                let mut ghost = piece;
+                ghost.sonic_drop(&game.board);
+                State::Falling(piece, ghost)
+            }
+            None => State::Delay,
+        };
+        self.garbage_queue = game.garbage_queue;
+        self.dead = game.is_game_over();
+        self.hold_piece = game.board.hold_piece;
+        self.next_queue = game.board.next_queue().collect();
+        self.combo_splash = None;
+        self.back_to_back_splash = None;
+        self.clear_splash = None;
+    }
+
+    pub fn draw_outline(&self, draw: &Draw, rect: Rect, outline: &Outline) {
+        let (play_area, mino_size) = play_area(rect);
+        let bl = play_area.bottom_left();
+
+        for &(x, y) in &outline.cells {
+            let (l, b) = (bl.x + x as f32 * mino_size, bl.y + y as f32 * mino_size);
+            let (r, t) = (l + mino_size, b + mino_size);
+            // only draw the edges that aren't shared with another cell of the same piece
+            let edges = [
+                ((x - 1, y), (l, b), (l, t)),
+                ((x + 1, y), (r, b), (r, t)),
+                ((x, y - 1), (l, b), (r, b)),
+                ((x, y + 1), (l, t), (r, t)),
+            ];
+            for (neighbour, (x0, y0), (x1, y1)) in edges {
+                if !outline.cells.contains(&neighbour) {
+                    draw.line()
+                        .start(Point2::new(x0, y0))
+                        .end(Point2::new(x1, y1))
+                        .weight(2.)
+                        .color(outline.color);
+                }
+            }
+        }
 
-        let mino_size = (rect.h() / VIS_BOARD as f32).floor();
+        if let Some(label) = &outline.label {
+            let &(x, y) = outline.cells.iter().max_by_key(|&&(_, y)| y).unwrap();
+            draw.text(label)
+                .x_y(bl.x + (x as f32 + 0.5) * mino_size, bl.y + (y as f32 + 1.5) * mino_size)
+                .font_size((mino_size / 2.) as u32)
+                .color(outline.color);
+        }
+    }
 
-        let play_area = Rect::from_xy_wh(rect.xy(), Vec2::new(mino_size * 10., mino_size * VIS_BOARD as f32)).floor();
+    pub fn draw(&self, draw: &Draw, rect: Rect, theme: &Theme) {
+        let (play_area, mino_size) = play_area(rect);
         draw.a::<PRect>(play_area.into())
-            .color(BLACK);
+            .color(theme.board_color());
+        if let Some(color) = theme.grid_color() {
+            for x in 1..10 {
+                let x = play_area.left() + x as f32 * mino_size;
+                draw.line()
+                    .start(Point2::new(x, play_area.bottom()))
+                    .end(Point2::new(x, play_area.top()))
+                    .color(color);
+            }
+            for y in 1..VIS_BOARD {
+                let y = play_area.bottom() + y as f32 * mino_size;
+                draw.line()
+                    .start(Point2::new(play_area.left(), y))
+                    .end(Point2::new(play_area.right(), y))
+                    .color(color);
+            }
+        }
 
         let bl = play_area.bottom_left();
         let mino_xy = |x: i32, y: i32| -> (f32, f32) {
@@ -203,10 +327,19 @@ impl GameDrawState {
             for x in 0..10 {
                 let color = row.cell_color(x);
                 let (x, y) = mino_xy(x as i32, y as i32);
-                self.skin.draw_mino(&draw, color, x, y, mino_size);
+                self.skin.draw_mino(&draw, color, x, y, mino_size, &theme.accessibility);
             }
         }
 
+        if self.garbage_queue > 0 {
+            let height = self.garbage_queue.min(VIS_BOARD as u32) as f32 * mino_size;
+            let meter = Rect::from_w_h(mino_size / 3., height)
+                .left_of(play_area)
+                .align_bottom_of(play_area);
+            draw.a::<PRect>(meter.into())
+                .color(theme.warning_color());
+        }
+
         #[inline]
         fn cell_color_from_piece(piece: Piece) -> CellColor {
             match piece {
@@ -223,13 +356,28 @@ impl GameDrawState {
         let draw_cells = |color: CellColor, cells: [(i32, i32); 4]| {
             for (x, y) in cells {
                 let (x, y) = mino_xy(x, y);
-                self.skin.draw_mino(&draw, color, x, y, mino_size);
+                self.skin.draw_mino(&draw, color, x, y, mino_size, &theme.accessibility);
             }
         };
 
         if let State::Falling(fall, ghost) = self.state {
             let color = cell_color_from_piece(fall.kind.0);
-            draw_cells(CellColor::Unclearable, ghost.cells());
+            match theme.ghost {
+                GhostStyle::Skin => draw_cells(CellColor::Unclearable, ghost.cells()),
+                GhostStyle::Outline => self.draw_outline(draw, rect, &Outline {
+                    cells: ghost.cells(),
+                    color: Rgba::new(1., 1., 1., 0.6),
+                    label: None,
+                }),
+                GhostStyle::Hidden => {}
+            }
+            if theme.accessibility.visible_ghost {
+                self.draw_outline(draw, rect, &Outline {
+                    cells: ghost.cells(),
+                    color: Rgba::new(1., 1., 1., 1.),
+                    label: None,
+                });
+            }
             draw_cells(color, fall.cells());
         }
 
@@ -240,7 +388,14 @@ impl GameDrawState {
                 _ => -0.5,
             };
             for (x, y) in piece.cells() {
-                self.skin.draw_mino(&draw, color, rect.x() + (x as f32 + x_offset) * mino_size, rect.y() + y as f32 * mino_size, mino_size);
+                self.skin.draw_mino(
+                    &draw,
+                    color,
+                    rect.x() + (x as f32 + x_offset) * mino_size,
+                    rect.y() + y as f32 * mino_size,
+                    mino_size,
+                    &theme.accessibility,
+                );
             }
         };
 
@@ -265,6 +420,15 @@ impl GameDrawState {
     }
 }
 
+const VIS_BOARD: usize = 20;
+
+/// The area of `rect` taken up by the board, along with the size of a single mino.
+fn play_area(rect: Rect) -> (Rect, f32) {
+    let mino_size = (rect.h() / VIS_BOARD as f32).floor();
+    let play_area = Rect::from_xy_wh(rect.xy(), Vec2::new(mino_size * 10., mino_size * VIS_BOARD as f32)).floor();
+    (play_area, mino_size)
+}
+
 fn letterbox(size: Rect) -> Rect {
     let d = size.w().min(size.h());
     Rect::from_xy_wh(size.xy(), Vec2::new(d, d))
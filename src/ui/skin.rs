@@ -1,6 +1,8 @@
 use libtetris::CellColor;
+use nannou::color::{rgba8, BLACK, WHITE};
 use nannou::Draw;
 
+use crate::ui::theme::Accessibility;
 use crate::wgpu::Texture;
 
 pub enum Skin {
@@ -8,7 +10,7 @@ pub enum Skin {
 }
 
 impl Skin {
-    pub fn draw_mino(&self, draw: &Draw, piece: CellColor, x: f32, y: f32, size: f32) {
+    pub fn draw_mino(&self, draw: &Draw, piece: CellColor, x: f32, y: f32, size: f32, accessibility: &Accessibility) {
         let Skin::Basic(texture) = &self;
         let Some(index) = color_to_tex_index(piece) else { return; };
 
@@ -26,6 +28,52 @@ impl Skin {
         ];
         draw.mesh()
             .points_textured(&texture, points);
+
+        let center = (x + size / 2., y + size / 2.);
+        let palette = accessibility.palette.color(piece);
+        if let Some(color) = palette {
+            // mostly covers the skin's colour, while some of its shading still shows
+            draw.rect()
+                .x_y(center.0, center.1)
+                .w_h(size, size)
+                .color(rgba8(color[0], color[1], color[2], 220));
+        }
+
+        if accessibility.outlines && piece != CellColor::Unclearable {
+            draw.rect()
+                .x_y(center.0, center.1)
+                .w_h(size, size)
+                .no_fill()
+                .stroke_weight((size / 12.).max(1.))
+                .stroke(BLACK);
+        }
+
+        if let (true, Some(glyph)) = (accessibility.glyphs, glyph(piece)) {
+            // dark letters on light minos and light letters on dark ones
+            let light = palette.map_or(true, |[r, g, b]| {
+                0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32 > 140.
+            });
+            draw.text(glyph)
+                .x_y(center.0, center.1)
+                .w_h(size, size)
+                .font_size((size * 0.6) as u32)
+                .color(if light { BLACK } else { WHITE })
+                .center_justify();
+        }
+    }
+}
+
+/// The letter drawn on minos of each piece when glyphs are enabled.
+fn glyph(color: CellColor) -> Option<&'static str> {
+    match color {
+        CellColor::I => Some("I"),
+        CellColor::O => Some("O"),
+        CellColor::T => Some("T"),
+        CellColor::L => Some("L"),
+        CellColor::J => Some("J"),
+        CellColor::S => Some("S"),
+        CellColor::Z => Some("Z"),
+        CellColor::Garbage | CellColor::Unclearable | CellColor::Empty => None,
     }
 }
 
@@ -0,0 +1,176 @@
+use std::path::{Path, PathBuf};
+
+use nannou::color::{rgb8, rgba8, Rgb8, Rgba8};
+use nannou::draw::primitive::Text;
+use nannou::draw::Drawing;
+use nannou::text::{font, Font};
+use nannou::Draw;
+use serde::{Deserialize, Serialize};
+
+use crate::config::APP_NAME;
+
+pub use practris_engine::config::{Accessibility, Palette, DEFAULT_THEME};
+
+pub const LIGHT_THEME: &str = "Light";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Background {
+    Color([u8; 3]),
+    /// An image stretched over the window.
+    Image(PathBuf),
+}
+
+/// How the ghost piece, showing where the falling piece would land, is drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GhostStyle {
+    /// With the skin's ghost mino.
+    Skin,
+    /// As an outline of the piece.
+    Outline,
+    Hidden,
+}
+
+/// How everything but the minos looks. Themes are kept as files in [`themes_dir`], so they can
+/// be shared; paths in them are relative to that directory.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub background: Background,
+    pub board: [u8; 3],
+    /// From 0 to 1.
+    pub board_opacity: f32,
+    /// The colour of lines between the cells of the board, if they're drawn.
+    pub grid: Option<[u8; 4]>,
+    pub ghost: GhostStyle,
+    /// The colour of the incoming garbage meter and the reset bar.
+    pub warning: [u8; 3],
+    /// A font file to draw text over the game with, instead of the default font.
+    pub hud_font: Option<PathBuf>,
+    /// How large text over the game is, relative to the default.
+    pub hud_scale: f32,
+    /// Whether windows and the settings panel are dark, rather than light.
+    pub dark_mode: bool,
+    /// `hud_font`, once loaded.
+    #[serde(skip)]
+    font: Option<Font>,
+    /// Set from the user's settings rather than the theme file, as it's personal.
+    #[serde(skip)]
+    pub accessibility: Accessibility,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            background: Background::Color([128, 128, 128]),
+            board: [0, 0, 0],
+            board_opacity: 1.,
+            grid: None,
+            ghost: GhostStyle::Skin,
+            warning: [255, 0, 0],
+            hud_font: None,
+            hud_scale: 1.,
+            dark_mode: true,
+            font: None,
+            accessibility: Accessibility::default(),
+        }
+    }
+}
+
+impl Theme {
+    pub fn light() -> Self {
+        Self {
+            background: Background::Color([225, 225, 230]),
+            board: [250, 250, 250],
+            grid: Some([0, 0, 0, 30]),
+            dark_mode: false,
+            ..Self::default()
+        }
+    }
+
+    pub fn board_color(&self) -> Rgba8 {
+        let [r, g, b] = self.board;
+        rgba8(r, g, b, (self.board_opacity.clamp(0., 1.) * 255.) as u8)
+    }
+
+    pub fn grid_color(&self) -> Option<Rgba8> {
+        self.grid.map(|[r, g, b, a]| rgba8(r, g, b, a))
+    }
+
+    pub fn warning_color(&self) -> Rgb8 {
+        let [r, g, b] = self.warning;
+        rgb8(r, g, b)
+    }
+
+    /// Draw text over the game, in the theme's font.
+    pub fn text<'a>(&self, draw: &'a Draw, text: &str) -> Drawing<'a, Text> {
+        let drawing = draw.text(text);
+        match &self.font {
+            Some(font) => drawing.font(font.clone()),
+            None => drawing,
+        }
+    }
+
+    /// The size of text over the game that would be `size` by default.
+    pub fn font_size(&self, size: f32) -> u32 {
+        (size * self.hud_scale) as u32
+    }
+
+    /// Make the paths in a theme read from `dir` absolute, and load its font.
+    fn resolve(&mut self, dir: &Path) -> Result<(), String> {
+        if let Background::Image(path) = &mut self.background {
+            *path = dir.join(&*path);
+        }
+        if let Some(path) = &mut self.hud_font {
+            *path = dir.join(&*path);
+            self.font = Some(font::from_file(&*path).map_err(|e| format!("Failed to read font {path:?}: {e}"))?);
+        }
+        Ok(())
+    }
+}
+
+pub fn themes_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join(APP_NAME).join("themes"))
+}
+
+/// The names of the built-in themes and those in [`themes_dir`].
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = themes_dir()
+        .and_then(|dir| std::fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "ron" {
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().into_owned())
+        })
+        .filter(|name| name != DEFAULT_THEME && name != LIGHT_THEME)
+        .collect();
+    names.sort();
+    [DEFAULT_THEME.to_string(), LIGHT_THEME.to_string()].into_iter().chain(names).collect()
+}
+
+pub fn load(name: &str) -> Result<Theme, String> {
+    match name {
+        DEFAULT_THEME => return Ok(Theme::default()),
+        LIGHT_THEME => return Ok(Theme::light()),
+        _ => {}
+    }
+    let dir = themes_dir().ok_or("Could not find the themes directory")?;
+    let src = std::fs::read_to_string(dir.join(name).with_extension("ron")).map_err(|e| e.to_string())?;
+    let mut theme: Theme = ron::from_str(&src).map_err(|e| e.to_string())?;
+    theme.resolve(&dir)?;
+    Ok(theme)
+}
+
+/// Save `theme` to [`themes_dir`] as `name`, to pick it again or share it.
+pub fn save(name: &str, theme: &Theme) -> Result<(), String> {
+    if name == DEFAULT_THEME || name == LIGHT_THEME {
+        return Err(format!("{name} is a built-in theme"));
+    }
+    let dir = themes_dir().ok_or("Could not find the themes directory")?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let src = ron::ser::to_string_pretty(theme, Default::default()).map_err(|e| e.to_string())?;
+    std::fs::write(dir.join(name).with_extension("ron"), src).map_err(|e| e.to_string())
+}
@@ -0,0 +1,368 @@
+use std::collections::HashSet;
+
+use gilrs::Gamepad;
+use nannou::prelude::*;
+use libtetris::Controller;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Event, Game, GameConfig, PlayerUpdate};
+use crate::input::{GameInput, InputEvent, InputSource, RawInput};
+use crate::ui::SingleplayerGameUi;
+use crate::ui::theme::Theme;
+use crate::wgpu::Texture;
+
+const START_TIME: u32 = 180;
+/// How long the result of a round is shown before the next one starts.
+const ROUND_END_TIME: u32 = 180;
+
+/// Which of the inputs available to the app a player listens to, so that two players can share
+/// one machine.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Device {
+    Keyboard,
+    Gamepad,
+    /// Both the keyboard and the gamepad, for when there's only one human player.
+    Any,
+    /// Neither, for players that aren't controlled by a human.
+    None,
+}
+
+/// What a player does on one tick.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TickInput {
+    pub controller: Controller,
+    /// Buttons pressed during the tick, see [`Game::update_with_presses`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub presses: Vec<GameInput>,
+    /// Whether reset is held, which starts a new match once the last one is over.
+    #[serde(default)]
+    pub reset: bool,
+}
+
+pub struct Player {
+    name: String,
+    game: Game,
+    ui: SingleplayerGameUi,
+    input: Box<dyn InputSource>,
+    device: Device,
+    piece_rng: Pcg64Mcg,
+    garbage_rng: Pcg64Mcg,
+    wins: u32,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum State {
+    Starting(u32),
+    Playing,
+    /// The round was won by the given player, and the next one starts when the delay runs out.
+    RoundOver(usize, u32),
+    /// The match was won by the given player. A new one starts when either player resets.
+    MatchOver(usize),
+}
+
+/// Two players, on one machine or against a bot, sending garbage to each other until one of them
+/// wins enough rounds.
+pub struct VersusGame {
+    players: [Player; 2],
+    state: State,
+    /// How many rounds have to be won to win the match.
+    first_to: u32,
+    config: GameConfig,
+    texture: Texture,
+    /// Decides the seeds of every round, so that a match plays out the same given the same inputs.
+    rng: Pcg64Mcg,
+    /// Whether each player held reset on the last tick.
+    prev_reset: [bool; 2],
+    /// The player whose events are kept, to play sounds for.
+    listened: usize,
+    /// The listened to player's events since they were last taken.
+    events: Vec<Event>,
+}
+
+impl Player {
+    pub fn new(name: String, input: Box<dyn InputSource>, device: Device, texture: Texture) -> Self {
+        let mut rng = Pcg64Mcg::from_seed([0; 16]);
+        let game = Game::new(GameConfig::fast_config(), &mut rng);
+        Self {
+            ui: SingleplayerGameUi::new(&game, name.clone(), texture),
+            name,
+            game,
+            input,
+            device,
+            piece_rng: rng,
+            garbage_rng: Pcg64Mcg::from_seed([0; 16]),
+            wins: 0,
+        }
+    }
+
+    /// Start a new round, with the same pieces as the opponent.
+    fn reset(&mut self, config: GameConfig, seed: [u8; 16], garbage_seed: [u8; 16], texture: Texture) {
+        self.piece_rng = Pcg64Mcg::from_seed(seed);
+        self.garbage_rng = Pcg64Mcg::from_seed(garbage_seed);
+        self.game = Game::new(config, &mut self.piece_rng);
+        self.ui = SingleplayerGameUi::new(&self.game, self.name.clone(), texture);
+        self.input.reset();
+    }
+
+    /// Only hand the player the inputs of the device they're using.
+    fn inputs<'a>(
+        &self,
+        keys: &'a HashSet<Key>,
+        no_keys: &'a HashSet<Key>,
+        gamepad: Option<Gamepad<'a>>,
+    ) -> (&'a HashSet<Key>, Option<Gamepad<'a>>) {
+        match self.device {
+            Device::Keyboard => (keys, None),
+            Device::Gamepad => (no_keys, gamepad),
+            Device::Any => (keys, gamepad),
+            Device::None => (no_keys, None),
+        }
+    }
+
+    fn tick_input(
+        &self,
+        keys: &HashSet<Key>,
+        gamepad: Option<Gamepad<'_>>,
+        inputs: &[InputEvent],
+    ) -> TickInput {
+        let no_keys = HashSet::new();
+        let (keys, gamepad) = self.inputs(keys, &no_keys, gamepad);
+        let inputs: Vec<InputEvent> = inputs.iter()
+            .filter(|event| match (event.input, self.device) {
+                (_, Device::Any) => true,
+                (RawInput::Key(_), Device::Keyboard) | (RawInput::Gamepad(_), Device::Gamepad) => true,
+                _ => false,
+            })
+            .copied()
+            .collect();
+        TickInput {
+            controller: self.input.controller(keys, gamepad),
+            presses: self.input.presses(&inputs),
+            reset: self.input.actions(keys, gamepad).reset,
+        }
+    }
+
+    fn update(&mut self, input: TickInput) -> Vec<Event> {
+        let events = self.game.update_with_presses(
+            input.controller,
+            &input.presses,
+            &mut self.piece_rng,
+            &mut self.garbage_rng,
+        );
+        self.input.update(&self.game.board, &events, self.game.garbage_queue);
+        events
+    }
+}
+
+impl VersusGame {
+    pub fn new(
+        players: [Player; 2],
+        first_to: u32,
+        config: GameConfig,
+        texture: Texture,
+        seed: [u8; 16],
+    ) -> Self {
+        let mut this = Self {
+            players,
+            state: State::Starting(START_TIME),
+            first_to,
+            config,
+            texture,
+            rng: Pcg64Mcg::from_seed(seed),
+            prev_reset: [false; 2],
+            listened: 0,
+            events: vec![],
+        };
+        this.new_round();
+        this
+    }
+
+    fn new_round(&mut self) {
+        let seed = self.rng.gen();
+        for player in &mut self.players {
+            player.reset(self.config, seed, self.rng.gen(), self.texture.clone());
+        }
+        self.state = State::Starting(START_TIME);
+    }
+
+    fn new_match(&mut self) {
+        for player in &mut self.players {
+            player.wins = 0;
+        }
+        self.new_round();
+    }
+
+    /// Keep the events of `player` rather than the first player's, for when the first player isn't
+    /// the one at this machine.
+    pub fn listen_to(&mut self, player: usize) {
+        self.listened = player;
+        self.events.clear();
+    }
+
+    /// The inputs of both players on this tick, given the keys and buttons pressed and released
+    /// since the last one.
+    pub fn tick_inputs(
+        &self,
+        keys: &HashSet<Key>,
+        gamepad: Option<Gamepad<'_>>,
+        inputs: &[InputEvent],
+    ) -> [TickInput; 2] {
+        [
+            self.players[0].tick_input(keys, gamepad, inputs),
+            self.players[1].tick_input(keys, gamepad, inputs),
+        ]
+    }
+
+    /// Advance the match by one tick. The result only depends on the seed and the inputs given
+    /// so far, which is what allows both sides of an online match to simulate it.
+    pub fn tick(&mut self, inputs: [TickInput; 2]) {
+        // only once the match is over, so a stray press can't throw away the wins so far
+        let reset = [0, 1].map(|idx| inputs[idx].reset);
+        let pressed = (0..2).any(|idx| reset[idx] && !self.prev_reset[idx]);
+        self.prev_reset = reset;
+        if pressed && matches!(self.state, State::MatchOver(_)) {
+            self.new_match();
+            return;
+        }
+
+        match self.state {
+            State::Starting(0) => {
+                self.state = State::Playing;
+                self.play_tick(inputs);
+            }
+            State::Starting(ref mut delay) => *delay -= 1,
+            State::Playing => self.play_tick(inputs),
+            State::RoundOver(_, 0) => self.new_round(),
+            State::RoundOver(_, ref mut delay) => *delay -= 1,
+            State::MatchOver(_) => {}
+        }
+    }
+
+    /// A hash of both boards, to check whether two simulations of the same match agree.
+    /// This is the same on every machine and build, unlike the standard library's hashers.
+    pub fn board_hash(&self) -> u64 {
+        let mut hash = Fnv1a::default();
+        for player in &self.players {
+            for row in player.game.board.get_field() {
+                hash.write(&row.map(u8::from));
+            }
+            hash.write(&player.game.garbage_queue.to_le_bytes());
+            hash.write(&player.wins.to_le_bytes());
+        }
+        hash.0
+    }
+
+    fn play_tick(&mut self, inputs: [TickInput; 2]) {
+        let [first, second] = inputs;
+        let events = [
+            self.players[0].update(first),
+            self.players[1].update(second),
+        ];
+
+        for (idx, events) in events.iter().enumerate() {
+            for event in events {
+                if let Event::GarbageSent(amount) = event {
+                    self.players[1 - idx].game.garbage_queue += amount;
+                }
+            }
+        }
+
+        self.events.extend(events[self.listened].iter().cloned());
+        let dead = [0, 1].map(|idx| events[idx].iter().any(|e| matches!(e, Event::GameOver(_))));
+        for (player, events) in self.players.iter_mut().zip(events) {
+            let garbage_queue = player.game.garbage_queue;
+            player.ui.update(PlayerUpdate { events, garbage_queue });
+        }
+
+        // if both players top out on the same tick, nobody wins the round
+        match dead {
+            [true, true] => self.state = State::RoundOver(usize::MAX, ROUND_END_TIME),
+            [false, false] => {}
+            [first_lost, _] => {
+                let winner = if first_lost { 1 } else { 0 };
+                self.players[winner].wins += 1;
+                self.state = if self.players[winner].wins >= self.first_to {
+                    State::MatchOver(winner)
+                } else {
+                    State::RoundOver(winner, ROUND_END_TIME)
+                };
+            }
+        }
+    }
+}
+
+impl crate::State for VersusGame {
+    fn update(&mut self, keys: &HashSet<Key>, gamepad: Option<Gamepad<'_>>, inputs: &[InputEvent]) {
+        let inputs = self.tick_inputs(keys, gamepad, inputs);
+        self.tick(inputs);
+    }
+
+    fn restart(&mut self) {
+        self.new_match();
+    }
+
+    fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn results(&self) -> Option<Vec<String>> {
+        let State::MatchOver(winner) = self.state else { return None; };
+        let [first, second] = &self.players;
+        Some(vec![
+            format!("{} wins the match!", self.players[winner].name),
+            format!("{} {} - {} {}", first.name, first.wins, second.wins, second.name),
+        ])
+    }
+
+    fn render(&self, draw: &Draw, rect: Rect, theme: &Theme) {
+        let (left, right) = (
+            Rect::from_corners(rect.top_left(), rect.mid_bottom()),
+            Rect::from_corners(rect.mid_top(), rect.bottom_right()),
+        );
+        let font_size = theme.font_size(rect.w() / 60.);
+
+        for (player, area) in self.players.iter().zip([left, right]) {
+            player.ui.draw(draw, area, theme);
+            theme.text(draw, &format!("{} - {} wins", player.name, player.wins))
+                .font_size(font_size)
+                .w(area.w())
+                .x_y(area.x(), area.top() - font_size as f32)
+                .center_justify();
+        }
+
+        let message = match self.state {
+            State::Starting(delay) => Some(format!("{}", delay / 60 + 1)),
+            State::RoundOver(usize::MAX, _) => Some("Draw".to_string()),
+            State::RoundOver(winner, _) => Some(format!("{} wins the round", self.players[winner].name)),
+            State::MatchOver(winner) => Some(format!("{} wins the match!", self.players[winner].name)),
+            State::Playing => None,
+        };
+        if let Some(message) = message {
+            theme.text(draw, &message)
+                .font_size(font_size * 2)
+                .w(rect.w())
+                .xy(rect.xy())
+                .center_justify();
+        }
+    }
+}
+
+/// The 64 bit FNV-1a hash.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
@@ -0,0 +1,380 @@
+#![feature(let_else)]
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Practris in a terminal, for practising on machines without a GPU or over SSH.
+//!
+//! This plays the same [`Singleplayer`] game as the window does, with the player's own settings,
+//! only drawn with coloured blocks instead.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::{cursor, execute, queue, terminal};
+use crossterm::event::{self, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags};
+use crossterm::style::{Color, Print, SetForegroundColor};
+use libtetris::{CellColor, Piece, PieceState, RotationState};
+use practris_engine::config::{self, Palette, UserSettings};
+use practris_engine::input::{InputEvent, Key, RawInput};
+use practris_engine::singleplayer::Singleplayer;
+use practris_engine::sound::SoundPlayer;
+
+const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// How long a key counts as held after the terminal last reported it, if it doesn't report
+/// releases. Longer than the time between key repeats, and shorter than the usual DAS.
+const HOLD_WINDOW: Duration = Duration::from_millis(75);
+const VISIBLE_ROWS: i32 = 20;
+/// Where the board is drawn, in terminal cells. Every mino is two characters wide.
+const BOARD_X: u16 = 12;
+const BOARD_Y: u16 = 1;
+const QUEUE_X: u16 = BOARD_X + 2 * 10 + 4;
+
+fn main() {
+    env_logger::init();
+
+    let profile = match std::env::args().skip(1).collect::<Vec<_>>().as_slice() {
+        [] => None,
+        [flag, profile] if flag == "--profile" => Some(profile.clone()),
+        _ => {
+            eprintln!("Usage: practris-tui [--profile <NAME>]");
+            std::process::exit(2);
+        }
+    };
+    let settings = config::settings_path(profile.as_deref())
+        .map(|path| config::load(&path))
+        .unwrap_or_default();
+
+    let mut stdout = io::stdout();
+    if let Err(e) = run(&mut stdout, settings) {
+        let _ = restore_terminal(&mut stdout);
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+    if let Err(e) = restore_terminal(&mut stdout) {
+        log::error!("Failed to restore the terminal: {e}");
+    }
+}
+
+fn restore_terminal(stdout: &mut Stdout) -> io::Result<()> {
+    if terminal::supports_keyboard_enhancement()? {
+        execute!(stdout, event::PopKeyboardEnhancementFlags)?;
+    }
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()
+}
+
+/// The keys that are held down.
+///
+/// Most terminals only report key presses, and repeat them while a key is held. There a key counts
+/// as held for as long as its repeats keep coming, so it's tapped when pressed and held once the
+/// terminal starts repeating it. Terminals that report releases are played like the window.
+struct Keys {
+    /// When each held key was last reported.
+    held: HashMap<Key, Instant>,
+    releases: bool,
+}
+
+impl Keys {
+    /// Take in a report of `key`, returning whether it was pressed or released rather than
+    /// repeated.
+    fn report(&mut self, key: Key, pressed: bool, now: Instant) -> bool {
+        if pressed {
+            self.held.insert(key, now).is_none()
+        } else {
+            self.held.remove(&key).is_some()
+        }
+    }
+
+    /// Release the keys the terminal stopped repeating.
+    fn expire(&mut self, now: Instant) -> Vec<Key> {
+        if self.releases {
+            return vec![];
+        }
+        let expired: Vec<Key> = self.held.iter()
+            .filter(|(_, &seen)| now.duration_since(seen) > HOLD_WINDOW)
+            .map(|(&key, _)| key)
+            .collect();
+        for key in &expired {
+            self.held.remove(key);
+        }
+        expired
+    }
+
+    fn held(&self) -> HashSet<Key> {
+        self.held.keys().copied().collect()
+    }
+}
+
+fn run(stdout: &mut Stdout, settings: UserSettings) -> io::Result<()> {
+    let mut sound = SoundPlayer::new(&settings.sound);
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide, terminal::Clear(terminal::ClearType::All))?;
+    let releases = terminal::supports_keyboard_enhancement()?;
+    if releases {
+        execute!(stdout, event::PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+    }
+
+    let mut game = Singleplayer::new(Box::new(settings.input));
+    game.set_config(settings.game);
+    game.restart();
+
+    let mut keys = Keys { held: HashMap::new(), releases };
+    let started = Instant::now();
+    let (width, height) = terminal::size()?;
+    let mut shown = Frame::new(width, height);
+    let mut next_tick = Instant::now();
+    loop {
+        let mut inputs = vec![];
+        while event::poll(next_tick.saturating_duration_since(Instant::now()))? {
+            let key_event = match event::read()? {
+                event::Event::Key(key_event) => key_event,
+                event::Event::Resize(width, height) => {
+                    queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+                    shown = Frame::new(width, height);
+                    continue;
+                }
+                _ => continue,
+            };
+            if key_event.code == KeyCode::Esc
+                || (key_event.code == KeyCode::Char('c') && key_event.modifiers.contains(KeyModifiers::CONTROL)) {
+                return Ok(());
+            }
+            let Some(key) = key(key_event.code) else { continue; };
+            let pressed = key_event.kind != KeyEventKind::Release;
+            if keys.report(key, pressed, Instant::now()) {
+                inputs.push(InputEvent { time: started.elapsed(), input: RawInput::Key(key), pressed });
+            }
+        }
+        next_tick += TICK;
+
+        for key in keys.expire(Instant::now()) {
+            inputs.push(InputEvent { time: started.elapsed(), input: RawInput::Key(key), pressed: false });
+        }
+
+        game.update(&keys.held(), None, &inputs);
+        sound.play(&game.take_events(), &settings.sound);
+
+        let mut frame = Frame::new(shown.width, shown.height);
+        draw(&mut frame, &game, settings.accessibility.palette);
+        frame.show(stdout, &shown)?;
+        shown = frame;
+    }
+}
+
+/// What every cell of the terminal shows, so that only the cells that changed since the last
+/// frame have to be written.
+struct Frame {
+    width: u16,
+    height: u16,
+    cells: Vec<(char, Color)>,
+}
+
+impl Frame {
+    fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![(' ', Color::Reset); width as usize * height as usize],
+        }
+    }
+
+    /// Write `text` starting at (`x`, `y`), cutting off whatever doesn't fit.
+    fn print(&mut self, x: u16, y: u16, text: &str, color: Color) {
+        if y >= self.height {
+            return;
+        }
+        for (x, c) in (x..self.width).zip(text.chars()) {
+            self.cells[y as usize * self.width as usize + x as usize] = (c, color);
+        }
+    }
+
+    /// Bring the terminal from showing `shown` to showing this frame.
+    fn show(&self, stdout: &mut Stdout, shown: &Frame) -> io::Result<()> {
+        // where the cursor is after the last write, to skip moving it to the very next cell
+        let mut cursor = None;
+        let mut color = None;
+        for (idx, (&cell, &old)) in self.cells.iter().zip(&shown.cells).enumerate() {
+            if cell == old {
+                continue;
+            }
+            let x = (idx % self.width as usize) as u16;
+            let y = (idx / self.width as usize) as u16;
+            if cursor != Some((x, y)) {
+                queue!(stdout, cursor::MoveTo(x, y))?;
+            }
+            if color != Some(cell.1) {
+                queue!(stdout, SetForegroundColor(cell.1))?;
+                color = Some(cell.1);
+            }
+            queue!(stdout, Print(cell.0))?;
+            cursor = Some((x + 1, y));
+        }
+        stdout.flush()
+    }
+}
+
+fn draw(frame: &mut Frame, game: &Singleplayer, palette: Palette) {
+    let board = game.game();
+
+    // the walls and floor of the board
+    for row in 0..VISIBLE_ROWS as u16 {
+        frame.print(BOARD_X - 1, BOARD_Y + row, "│", Color::Reset);
+        frame.print(BOARD_X + 20, BOARD_Y + row, "│", Color::Reset);
+    }
+    frame.print(BOARD_X - 1, BOARD_Y + VISIBLE_ROWS as u16, &format!("└{}┘", "─".repeat(20)), Color::Reset);
+
+    for y in 0..VISIBLE_ROWS {
+        let row = board.board.get_row(y);
+        for x in 0..10 {
+            let color = row.cell_color(x as usize);
+            if color != CellColor::Empty {
+                draw_mino(frame, x, y, terminal_color(color, palette), "██");
+            }
+        }
+    }
+
+    if let Some(piece) = board.falling_piece() {
+        let color = terminal_color(piece_color(piece.kind.0), palette);
+        let mut ghost = piece;
+        ghost.sonic_drop(&board.board);
+        for (x, y) in ghost.cells() {
+            draw_mino(frame, x, y, color, "[]");
+        }
+        for (x, y) in piece.cells() {
+            draw_mino(frame, x, y, color, "██");
+        }
+    }
+
+    frame.print(1, BOARD_Y, "HOLD", Color::Reset);
+    if let Some(piece) = board.board.hold_piece {
+        draw_preview(frame, 2, BOARD_Y + 3, piece, palette);
+    }
+
+    frame.print(QUEUE_X, BOARD_Y, "NEXT", Color::Reset);
+    for (idx, piece) in board.board.next_queue().take(5).enumerate() {
+        draw_preview(frame, QUEUE_X + 1, BOARD_Y + 3 + 3 * idx as u16, piece, palette);
+    }
+
+    // why the game ended while it's still ending, and the results once it's over
+    let message = game.results()
+        .or_else(|| board.game_over_reason().map(|reason| vec![reason.to_string()]));
+    for (idx, line) in message.iter().flatten().enumerate() {
+        frame.print(BOARD_X + 1, BOARD_Y + VISIBLE_ROWS as u16 / 2 + idx as u16, line, Color::Reset);
+    }
+}
+
+/// Draw a mino of the board, which is `y` rows up from its bottom.
+fn draw_mino(frame: &mut Frame, x: i32, y: i32, color: Color, glyph: &str) {
+    if !(0..VISIBLE_ROWS).contains(&y) || !(0..10).contains(&x) {
+        return;
+    }
+    frame.print(BOARD_X + 2 * x as u16, BOARD_Y + (VISIBLE_ROWS - 1 - y) as u16, glyph, color);
+}
+
+/// Draw `piece` in its spawn orientation, with the bottom row of its bounding box at `bottom`.
+fn draw_preview(frame: &mut Frame, left: u16, bottom: u16, piece: Piece, palette: Palette) {
+    let color = terminal_color(piece_color(piece), palette);
+    for (x, y) in PieceState(piece, RotationState::North).cells() {
+        frame.print(left + 2 * (x + 1) as u16, bottom - y as u16, "██", color);
+    }
+}
+
+fn piece_color(piece: Piece) -> CellColor {
+    match piece {
+        Piece::I => CellColor::I,
+        Piece::O => CellColor::O,
+        Piece::T => CellColor::T,
+        Piece::L => CellColor::L,
+        Piece::J => CellColor::J,
+        Piece::S => CellColor::S,
+        Piece::Z => CellColor::Z,
+    }
+}
+
+/// The colour `color` is drawn in, in the player's palette if they picked one.
+fn terminal_color(color: CellColor, palette: Palette) -> Color {
+    if let Some([r, g, b]) = palette.color(color) {
+        return Color::Rgb { r, g, b };
+    }
+    match color {
+        CellColor::I => Color::Cyan,
+        CellColor::O => Color::Yellow,
+        CellColor::T => Color::Magenta,
+        CellColor::L => Color::DarkYellow,
+        CellColor::J => Color::Blue,
+        CellColor::S => Color::Green,
+        CellColor::Z => Color::Red,
+        CellColor::Garbage | CellColor::Unclearable => Color::Grey,
+        CellColor::Empty => Color::Reset,
+    }
+}
+
+/// The key `code` is bound as in the settings, which are shared with the window.
+fn key(code: KeyCode) -> Option<Key> {
+    Some(match code {
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Enter => Key::Return,
+        KeyCode::Backspace => Key::Back,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::Char(c) => match c.to_ascii_lowercase() {
+            ' ' => Key::Space,
+            'a' => Key::A,
+            'b' => Key::B,
+            'c' => Key::C,
+            'd' => Key::D,
+            'e' => Key::E,
+            'f' => Key::F,
+            'g' => Key::G,
+            'h' => Key::H,
+            'i' => Key::I,
+            'j' => Key::J,
+            'k' => Key::K,
+            'l' => Key::L,
+            'm' => Key::M,
+            'n' => Key::N,
+            'o' => Key::O,
+            'p' => Key::P,
+            'q' => Key::Q,
+            'r' => Key::R,
+            's' => Key::S,
+            't' => Key::T,
+            'u' => Key::U,
+            'v' => Key::V,
+            'w' => Key::W,
+            'x' => Key::X,
+            'y' => Key::Y,
+            'z' => Key::Z,
+            '0' => Key::Key0,
+            '1' => Key::Key1,
+            '2' => Key::Key2,
+            '3' => Key::Key3,
+            '4' => Key::Key4,
+            '5' => Key::Key5,
+            '6' => Key::Key6,
+            '7' => Key::Key7,
+            '8' => Key::Key8,
+            '9' => Key::Key9,
+            ',' => Key::Comma,
+            '.' => Key::Period,
+            '/' => Key::Slash,
+            ';' => Key::Semicolon,
+            '\'' => Key::Apostrophe,
+            '-' => Key::Minus,
+            '=' => Key::Equals,
+            '[' => Key::LBracket,
+            ']' => Key::RBracket,
+            '\\' => Key::Backslash,
+            _ => return None,
+        },
+        _ => return None,
+    })
+}